@@ -1,8 +1,12 @@
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use lazy_static::lazy_static;
+
+use crate::error_report::{ErrorReport, ErrorReportSender};
 
 #[cfg(unix)]
 use libc;
@@ -24,6 +28,36 @@ pub const COMMAND_LONGPOLL_TIMEOUT_SECS: u64 = 60;
 /// How long to back off on HTTP errors
 pub const COMMAND_RETRY_BACKOFF_SECS: u64 = 5;
 
+/// How often a running command's cancellation flag is refreshed from the
+/// server (see `spawn_cancel_watcher`). Frequent enough that a long shell
+/// command gets killed promptly after an operator cancels it, without
+/// hammering `command_status` every tick of the child's own poll loop.
+pub const CANCEL_POLL_INTERVAL_SECS: u64 = 5;
+
+/// What a `CommandSpec::Package` action should do to `packages`. Mirrors
+/// the handful of operations every mainstream package manager supports, so
+/// `command::execute_command` can map one of these onto the right backend
+/// invocation instead of the server having to know apt from dnf from
+/// winget.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageOp {
+    Install,
+    Remove,
+    Upgrade,
+    UpgradeAll,
+    ListUpdates,
+}
+
+/// One package with an update pending, as reported back by a
+/// `PackageOp::ListUpdates` run (see `command::parse_list_updates`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackageUpdate {
+    pub name: String,
+    pub current_version: Option<String>,
+    pub available_version: Option<String>,
+}
+
 /// A structured representation of what to run
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -40,6 +74,47 @@ pub enum CommandSpec {
         args: Option<Vec<String>>,
         timeout_secs: Option<u64>,
     },
+
+    /// A package-manager operation, e.g. "install nginx" or "list pending
+    /// updates" — handled without a hand-written shell string per OS. If
+    /// `manager` is `None`, `command::detect_package_manager` picks the
+    /// first one present on this host (apt/apt-get, dnf/yum, zypper, or
+    /// pacman on Unix; winget or choco on Windows).
+    #[serde(rename = "package")]
+    Package {
+        manager: Option<String>,
+        operation: PackageOp,
+        #[serde(default)]
+        packages: Vec<String>,
+        #[serde(default)]
+        assume_yes: bool,
+    },
+
+    /// An interactive PTY-backed shell session — see `command::run_pty_session`,
+    /// which drives it over the exact `/shell/<session>/...` routes
+    /// `routes/shell.rs` exposes. Unlike the other variants it has no
+    /// `timeout_secs`: it's bounded by this action's own `expires_at`/
+    /// cancellation, the same lifecycle every other command already gets
+    /// from `is_expired`/`is_canceled`, rather than a separate deadline.
+    #[serde(rename = "pty")]
+    Pty {
+        shell: Option<String>,
+    },
+
+    /// A filesystem watch — see `command::run_watch_session`, which drives
+    /// `watcher::WatchSession` over the `/watch/<action_id>/...` routes
+    /// `routes/watch.rs` exposes. Like `Pty`, it has no `timeout_secs`: it
+    /// runs until this action's own `expires_at`/cancellation ends it.
+    #[serde(rename = "watch")]
+    Watch {
+        paths: Vec<String>,
+        recursive: Option<bool>,
+        debounce_ms: Option<u64>,
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
 }
 
 /// A command received from the server
@@ -48,9 +123,49 @@ pub struct ServerCommand {
     pub id: String,
     pub spec: CommandSpec,
     pub created_at: Option<String>,
+    /// RFC3339 deadline past which the action is no longer valid to run,
+    /// mirrored from the server's `actions.expires_at`. `None` means the
+    /// delivery path didn't carry one (e.g. an older server) — treated as
+    /// "never expires" rather than rejected outright.
+    pub expires_at: Option<String>,
     pub run_as_root: Option<bool>,
 }
 
+/// Where a `ServerCommand` is at in its life.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    TimedOut,
+    /// Canceled by an operator (`/api/actions/<id>` cancel) or mid-run
+    /// abort — see `command::execute_command`'s cancellation poll.
+    Canceled,
+    /// Past `expires_at` before execution ever started.
+    Expired,
+}
+
+/// Which of the child's two pipes an [`OutputFrame`] came from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// One chunk (a single pipe read) of incremental output, posted to
+/// `/api/devices/{id}/commands/{cmd}/output` as it's produced instead of
+/// waiting for the command to finish. `seq` is shared across both streams
+/// so the server can reconstruct interleaving order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputFrame {
+    pub seq: u64,
+    pub stream: OutputStream,
+    pub data: String,
+}
+
 /// A summary of execution for posting back
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CommandResult {
@@ -60,6 +175,13 @@ pub struct CommandResult {
     pub stderr: String,
     pub duration_secs: f64,
     pub success: bool,
+    pub state: CommandState,
+    /// Populated only for a `PackageOp::ListUpdates` run — the structured
+    /// list `command::parse_list_updates` got out of the backend's output.
+    /// This is what should feed a device's `updates_available` going
+    /// forward instead of a bare bool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_updates: Option<Vec<PackageUpdate>>,
 }
 
 /// Check whether we are running with root/admin privileges
@@ -76,18 +198,31 @@ fn check_admin(_required: bool) -> Result<()> {
     Ok(())
 }
 
-/// Poll the server once for new commands
+/// Poll the server once for new commands. Long-polls for up to
+/// `COMMAND_LONGPOLL_TIMEOUT_SECS`: the server holds the connection open
+/// until a command is queued or the window elapses, returning an empty
+/// body on timeout — so this mostly blocks, rather than spinning on a
+/// fixed interval, without hammering the endpoint.
 pub async fn poll_for_commands_once(
     client: &Client,
     server_url: &str,
     device_id: &str,
 ) -> Result<Vec<ServerCommand>> {
-    log::debug!("Polling server for commands for device {}", device_id);
+    log::debug!("Long-polling server for commands for device {}", device_id);
 
-    let resp = client
-        .get(format!("{}/api/devices/{}/commands/poll", server_url, device_id))
-        .send()
-        .await?;
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c
+            .get(format!("{}/api/devices/{}/commands/poll", server_url, device_id))
+            .query(&[("wait", COMMAND_LONGPOLL_TIMEOUT_SECS)])
+            .timeout(std::time::Duration::from_secs(
+                COMMAND_LONGPOLL_TIMEOUT_SECS + 10,
+            ));
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
 
     if !resp.status().is_success() {
         log::warn!("Command poll rejected: {}", resp.status());
@@ -105,13 +240,145 @@ pub async fn poll_for_commands_once(
     Ok(out)
 }
 
+/// Whether `cmd.expires_at` (when present) is already in the past.
+pub(crate) fn is_expired(cmd: &ServerCommand) -> bool {
+    match &cmd.expires_at {
+        Some(raw) => match DateTime::parse_from_rfc3339(raw) {
+            Ok(dt) => dt.with_timezone(&Utc) <= Utc::now(),
+            // An unparsable deadline shouldn't block execution — treat it
+            // the same as "no deadline given".
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
+
+/// Poll `/commands/<id>/status` once for whether the action behind `cmd`
+/// has been canceled. Best-effort: a poll failure (offline, server down)
+/// is treated as "not canceled yet" rather than aborting the command on a
+/// transient network blip.
+pub(crate) async fn is_canceled(client: &Client, server_url: &str, device_id: &str, cmd_id: &str) -> bool {
+    let url = format!(
+        "{}/api/devices/{}/commands/{}/status",
+        server_url.trim_end_matches('/'),
+        device_id,
+        cmd_id
+    );
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.get(&url);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await;
+
+    match resp {
+        Ok(resp) if resp.status().is_success() => resp
+            .json::<Value>()
+            .await
+            .ok()
+            .and_then(|v| v.get("canceled").and_then(Value::as_bool))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Spawn a task that repeatedly polls `is_canceled` every
+/// [`CANCEL_POLL_INTERVAL_SECS`] and flips `flag` the moment it sees the
+/// action canceled, so `command::execute_command`'s run loop can abort an
+/// in-flight child instead of only checking once up front. Cancel the
+/// returned handle once the command reaches a terminal state either way.
+fn spawn_cancel_watcher(
+    client: Client,
+    server_url: String,
+    device_id: String,
+    cmd_id: String,
+    flag: Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(CANCEL_POLL_INTERVAL_SECS)).await;
+            if is_canceled(&client, &server_url, &device_id, &cmd_id).await {
+                flag.store(true, Ordering::SeqCst);
+                return;
+            }
+        }
+    })
+}
+
+/// Build the terminal `CommandResult` for a command that never ran because
+/// it was already expired or canceled by the time we looked.
+fn skipped_result(cmd: &ServerCommand, state: CommandState) -> CommandResult {
+    CommandResult {
+        id: cmd.id.clone(),
+        exit_code: -1,
+        stdout: String::new(),
+        stderr: String::new(),
+        duration_secs: 0.0,
+        success: false,
+        state,
+        package_updates: None,
+    }
+}
+
+/// Hard cap on how many command ids we remember, so a long-running agent
+/// that's processed many commands doesn't grow this cache without bound.
+const SEEN_COMMAND_CACHE_CAPACITY: usize = 4096;
+
+/// Bounded, FIFO-evicted set of command ids `execute_action` has already
+/// started. The server's bearer-token-authenticated channel already rules
+/// out a forged/replayed command the way `remote_cmd`'s old HMAC nonce
+/// scheme had to, but nothing stops the same id from being delivered twice
+/// by an overlapping poll or a server-side retry — this is just dedup
+/// against that, not a security boundary.
+struct SeenCommandCache {
+    seen: std::collections::HashSet<String>,
+    order: std::collections::VecDeque<String>,
+}
+
+impl SeenCommandCache {
+    fn new() -> Self {
+        Self {
+            seen: std::collections::HashSet::new(),
+            order: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if `id` was already recorded (a duplicate delivery).
+    /// Otherwise records it and returns `false`.
+    fn check_and_insert(&mut self, id: &str) -> bool {
+        if self.seen.contains(id) {
+            return true;
+        }
+        self.seen.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        while self.order.len() > SEEN_COMMAND_CACHE_CAPACITY {
+            if let Some(evicted) = self.order.pop_front() {
+                self.seen.remove(&evicted);
+            }
+        }
+        false
+    }
+}
+
+lazy_static! {
+    static ref SEEN_COMMANDS: std::sync::Mutex<SeenCommandCache> = std::sync::Mutex::new(SeenCommandCache::new());
+}
+
 /// Execute a command via the engine in `command.rs`
 pub async fn execute_action(
     client: Client,
     server_url: String,
     device_id: String,
     cmd: ServerCommand,
+    report_tx: ErrorReportSender,
 ) {
+    if SEEN_COMMANDS.lock().unwrap().check_and_insert(&cmd.id) {
+        log::info!("Skipping duplicate delivery of command {}", cmd.id);
+        return;
+    }
+
     if let Some(run_as_root) = cmd.run_as_root {
         #[cfg(unix)]
         if let Err(e) = check_root(run_as_root) {
@@ -122,8 +389,123 @@ pub async fn execute_action(
         let _ = check_admin(run_as_root);
     }
 
-    // Delegate actual execution to engine
-    let exec_result = crate::command::execute_command(cmd.clone()).await;
+    // Honor the action's own lifecycle before doing any work: a command
+    // that's past `expires_at` or already flagged canceled has no business
+    // running at all.
+    if is_expired(&cmd) {
+        log::info!("Skipping expired command {}", cmd.id);
+        let result = skipped_result(&cmd, CommandState::Expired);
+        crate::spool::queue_and_send_result(&client, &server_url, &device_id, result).await;
+        let _ = report_tx.send(ErrorReport {
+            device_id: device_id.clone(),
+            cmd_id: cmd.id.clone(),
+            phase: "expired".into(),
+            message: "command skipped: past expires_at".into(),
+        });
+        return;
+    }
+    if is_canceled(&client, &server_url, &device_id, &cmd.id).await {
+        log::info!("Skipping canceled command {}", cmd.id);
+        let result = skipped_result(&cmd, CommandState::Canceled);
+        crate::spool::queue_and_send_result(&client, &server_url, &device_id, result).await;
+        let _ = report_tx.send(ErrorReport {
+            device_id: device_id.clone(),
+            cmd_id: cmd.id.clone(),
+            phase: "canceled".into(),
+            message: "command skipped: action canceled before it started".into(),
+        });
+        return;
+    }
+
+    // A PTY session is a long-lived, bidirectional stream rather than a
+    // one-shot subprocess — it doesn't fit the spool/cancel-watcher/
+    // CommandResult machinery below (`command::run_pty_session` does its
+    // own cancellation/expiry polling in its place), so it's dispatched
+    // directly and returns here rather than falling through.
+    if let CommandSpec::Pty { shell } = cmd.spec.clone() {
+        // Mirrors the server's own `required_capability` gate on "pty"
+        // actions (routes/actions.rs) — reject up front if an older/
+        // misconfigured server dispatched one anyway, rather than opening
+        // a PTY the server was never supposed to send us.
+        if !crate::device::has_negotiated_capability("pty") {
+            log::warn!("Skipping PTY command {}: 'pty' capability not negotiated", cmd.id);
+            let result = skipped_result(&cmd, CommandState::Failed);
+            crate::spool::queue_and_send_result(&client, &server_url, &device_id, result).await;
+            let _ = report_tx.send(ErrorReport {
+                device_id: device_id.clone(),
+                cmd_id: cmd.id.clone(),
+                phase: "capability".into(),
+                message: "command requires capability 'pty', which was not negotiated with the server".into(),
+            });
+            return;
+        }
+        if let Err(e) = crate::command::run_pty_session(&client, &server_url, &device_id, &cmd, shell).await {
+            log::warn!("PTY session {} failed: {:?}", cmd.id, e);
+            let _ = report_tx.send(ErrorReport {
+                device_id: device_id.clone(),
+                cmd_id: cmd.id.clone(),
+                phase: "execution".into(),
+                message: format!("{:?}", e),
+            });
+        }
+        return;
+    }
+
+    // Likewise, a filesystem watch runs until this action's own
+    // expires_at/cancellation ends it rather than completing once — see
+    // `command::run_watch_session`. No capability gate here: unlike "pty",
+    // the server's own `required_capability` (routes/actions.rs) doesn't
+    // list "watch" and dispatches it unconditionally.
+    if let CommandSpec::Watch { paths, recursive, debounce_ms, include, exclude } = cmd.spec.clone() {
+        if let Err(e) = crate::command::run_watch_session(
+            &client,
+            &server_url,
+            &device_id,
+            &cmd,
+            paths,
+            recursive,
+            debounce_ms,
+            include,
+            exclude,
+        )
+        .await
+        {
+            log::warn!("Watch session {} failed: {:?}", cmd.id, e);
+            let _ = report_tx.send(ErrorReport {
+                device_id: device_id.clone(),
+                cmd_id: cmd.id.clone(),
+                phase: "execution".into(),
+                message: format!("{:?}", e),
+            });
+        }
+        return;
+    }
+
+    // Persist the command before it starts running so a crash/reboot
+    // mid-execution still gets it replayed by `action_loop` on the next
+    // startup (see `spool::replay_inbound`) instead of silently dropping
+    // it.
+    crate::spool::enqueue_inbound(&cmd).await;
+
+    // Polled by `command::execute_command` so an operator cancelling a
+    // long-running command mid-flight gets it killed rather than having to
+    // wait out `COMMAND_EXEC_TIMEOUT_SECS`.
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    let watcher = spawn_cancel_watcher(
+        client.clone(),
+        server_url.clone(),
+        device_id.clone(),
+        cmd.id.clone(),
+        cancel_flag.clone(),
+    );
+
+    // Delegate actual execution to engine. Output is streamed to the
+    // server as it's produced (see `command::execute_command`); only the
+    // terminal summary is posted here.
+    let exec_result =
+        crate::command::execute_command(&client, &server_url, &device_id, cmd.clone(), cancel_flag)
+            .await;
+    watcher.abort();
 
     match exec_result {
         Ok(execution) => {
@@ -133,24 +515,31 @@ pub async fn execute_action(
                 exit_code: execution.exit_code,
                 stdout: execution.stdout.clone(),
                 stderr: execution.stderr.clone(),
-                duration_secs: 0.0,
-                success: execution.exit_code == 0,
+                duration_secs: execution.duration_secs,
+                success: execution.state == CommandState::Completed,
+                state: execution.state,
+                package_updates: execution.package_updates.clone(),
             };
 
-            if let Err(e) = crate::command::post_command_result(
-                &client,
-                &server_url,
-                &execution.id,
-                &result,
-            ).await
-            {
-                log::warn!("Failed to post result for {}: {}", cmd.id, e);
-            }
+            // Queued to the durable outbound spool before the send is even
+            // attempted, so a failed/offline post is retried by
+            // `spool::run_flush_task` rather than lost (see `spool.rs`).
+            crate::spool::queue_and_send_result(&client, &server_url, &device_id, result).await;
         }
         Err(e) => {
             log::warn!("Execution failed for {}: {:?}", cmd.id, e);
+            let _ = report_tx.send(ErrorReport {
+                device_id: device_id.clone(),
+                cmd_id: cmd.id.clone(),
+                phase: "execution".into(),
+                message: format!("{:?}", e),
+            });
         }
     }
+
+    // Reached a terminal state either way — the result (or the fact that
+    // it failed to even start) no longer needs to be replayed on restart.
+    crate::spool::dequeue_inbound(&cmd.id).await;
 }
 
 /// Action loop: poll continuously and dispatch
@@ -159,7 +548,31 @@ pub async fn action_loop(
     server_url: String,
     device_id: String,
     running_flag: Option<Arc<AtomicBool>>,
+    report_tx: ErrorReportSender,
 ) -> Result<()> {
+    // A command left in the inbound spool from a previous run never
+    // reached a terminal state (the process died mid-execution) — run it
+    // again rather than letting it vanish.
+    for cmd in crate::spool::replay_inbound().await {
+        log::info!("Replaying inbound command {} left over from a previous run", cmd.id);
+        let c = client.clone();
+        let s = server_url.clone();
+        let d = device_id.clone();
+        let rt = report_tx.clone();
+        tokio::spawn(async move {
+            execute_action(c, s, d, cmd, rt).await;
+        });
+    }
+
+    // Dedicated task that retries whatever's left in the outbound spool
+    // (results/output frames that couldn't be sent right away) with
+    // backoff, independent of this loop's own polling.
+    tokio::spawn(crate::spool::run_flush_task(
+        client.clone(),
+        server_url.clone(),
+        running_flag.clone(),
+    ));
+
     loop {
         if let Some(flag) = &running_flag {
             if !flag.load(Ordering::SeqCst) {
@@ -168,17 +581,28 @@ pub async fn action_loop(
             }
         }
 
-        let commands = poll_for_commands_once(&client, &server_url, &device_id).await?;
-        for cmd in commands {
-            let c = client.clone();
-            let s = server_url.clone();
-            let d = device_id.clone();
-            tokio::spawn(async move {
-                execute_action(c, s, d, cmd).await;
-            });
+        match poll_for_commands_once(&client, &server_url, &device_id).await {
+            Ok(commands) if commands.is_empty() => {
+                tokio::time::sleep(std::time::Duration::from_secs(COMMAND_POLL_INTERVAL_SECS)).await;
+            }
+            Ok(commands) => {
+                for cmd in commands {
+                    let c = client.clone();
+                    let s = server_url.clone();
+                    let d = device_id.clone();
+                    let rt = report_tx.clone();
+                    tokio::spawn(async move {
+                        execute_action(c, s, d, cmd, rt).await;
+                    });
+                }
+                // A command was just delivered — re-poll immediately
+                // instead of waiting out the usual interval.
+            }
+            Err(e) => {
+                log::warn!("Command poll failed: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(COMMAND_RETRY_BACKOFF_SECS)).await;
+            }
         }
-
-        tokio::time::sleep(std::time::Duration::from_secs(COMMAND_POLL_INTERVAL_SECS)).await;
     }
 
     Ok(())
@@ -1,8 +1,17 @@
 use anyhow::Result;
 use reqwest::Client;
-use tokio::task;
-use std::process::Command;
-use crate::action::{CommandSpec, ServerCommand, CommandResult};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Duration};
+use crate::action::{
+    CommandResult, CommandSpec, CommandState, OutputFrame, OutputStream, PackageOp,
+    PackageUpdate, ServerCommand, COMMAND_EXEC_TIMEOUT_SECS,
+};
+use crate::pty_shell::{ShellControl, ShellFrame, ShellSession};
 
 /// A structured execution result
 #[derive(Debug, Clone)]
@@ -11,12 +20,237 @@ pub struct ExecutionResult {
     pub stdout: String,
     pub stderr: String,
     pub exit_code: i32,
+    pub duration_secs: f64,
+    pub state: CommandState,
+    /// Set only for a `PackageOp::ListUpdates` run (see
+    /// `parse_list_updates`).
+    pub package_updates: Option<Vec<PackageUpdate>>,
 }
 
-/// Run a single command spec
-pub async fn execute_command(cmd: ServerCommand) -> Result<ExecutionResult> {
+/// Package managers this agent knows how to drive, in the order
+/// `detect_package_manager` probes for them.
+#[cfg(not(windows))]
+const UNIX_PACKAGE_MANAGERS: &[&str] = &["apt-get", "apt", "dnf", "yum", "zypper", "pacman"];
+#[cfg(windows)]
+const WINDOWS_PACKAGE_MANAGERS: &[&str] = &["winget", "choco"];
+
+/// Find the first package manager on [`UNIX_PACKAGE_MANAGERS`] /
+/// [`WINDOWS_PACKAGE_MANAGERS`] that's actually on this host's `PATH`, for
+/// a `CommandSpec::Package { manager: None, .. }` action.
+fn detect_package_manager() -> Result<String> {
+    #[cfg(not(windows))]
+    let candidates = UNIX_PACKAGE_MANAGERS;
+    #[cfg(windows)]
+    let candidates = WINDOWS_PACKAGE_MANAGERS;
+
+    for candidate in candidates {
+        let found = Command::new("sh")
+            .arg("-c")
+            .arg(format!("command -v {candidate}"))
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+
+        #[cfg(windows)]
+        let found = Command::new("where")
+            .arg(candidate)
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+
+        if found {
+            return Ok(candidate.to_string());
+        }
+    }
+
+    anyhow::bail!("no supported package manager found on this host")
+}
+
+/// Build the `(program, args)` for one `CommandSpec::Package` operation.
+/// `ListUpdates` intentionally ignores `packages`/`assume_yes` — it's a
+/// read-only query.
+fn package_command(
+    manager: &str,
+    operation: PackageOp,
+    packages: &[String],
+    assume_yes: bool,
+) -> Result<(String, Vec<String>)> {
+    let yes = |flag: &str| -> Vec<String> {
+        if assume_yes { vec![flag.to_string()] } else { vec![] }
+    };
+
+    let args: Vec<String> = match manager {
+        "apt-get" | "apt" => match operation {
+            PackageOp::Install => [vec!["install".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Remove => [vec!["remove".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Upgrade => {
+                [vec!["install".into(), "--only-upgrade".into()], yes("-y"), packages.to_vec()].concat()
+            }
+            PackageOp::UpgradeAll => [yes("-y"), vec!["upgrade".into()]].concat(),
+            PackageOp::ListUpdates => vec!["list".into(), "--upgradable".into()],
+        },
+        "dnf" | "yum" => match operation {
+            PackageOp::Install => [vec!["install".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Remove => [vec!["remove".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Upgrade => [vec!["upgrade".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::UpgradeAll => [vec!["upgrade".into()], yes("-y")].concat(),
+            PackageOp::ListUpdates => vec!["check-update".into()],
+        },
+        "zypper" => {
+            let non_interactive = if assume_yes { vec!["--non-interactive".to_string()] } else { vec![] };
+            match operation {
+                PackageOp::Install => [non_interactive, vec!["install".into()], packages.to_vec()].concat(),
+                PackageOp::Remove => [non_interactive, vec!["remove".into()], packages.to_vec()].concat(),
+                PackageOp::Upgrade => [non_interactive, vec!["update".into()], packages.to_vec()].concat(),
+                PackageOp::UpgradeAll => [non_interactive, vec!["update".into()]].concat(),
+                PackageOp::ListUpdates => vec!["list-updates".into()],
+            }
+        }
+        "pacman" => match operation {
+            PackageOp::Install => [vec!["-S".into()], yes("--noconfirm"), packages.to_vec()].concat(),
+            PackageOp::Remove => [vec!["-R".into()], yes("--noconfirm"), packages.to_vec()].concat(),
+            PackageOp::Upgrade => [vec!["-S".into()], yes("--noconfirm"), packages.to_vec()].concat(),
+            PackageOp::UpgradeAll => [vec!["-Syu".into()], yes("--noconfirm")].concat(),
+            PackageOp::ListUpdates => vec!["-Qu".into()],
+        },
+        "winget" => match operation {
+            PackageOp::Install => {
+                [vec!["install".into(), "--accept-package-agreements".into(), "--accept-source-agreements".into()], yes("--silent"), packages.to_vec()].concat()
+            }
+            PackageOp::Remove => [vec!["uninstall".into()], packages.to_vec()].concat(),
+            PackageOp::Upgrade => [vec!["upgrade".into()], packages.to_vec()].concat(),
+            PackageOp::UpgradeAll => vec!["upgrade".into(), "--all".into()],
+            PackageOp::ListUpdates => vec!["upgrade".into()],
+        },
+        "choco" => match operation {
+            PackageOp::Install => [vec!["install".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Remove => [vec!["uninstall".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::Upgrade => [vec!["upgrade".into()], yes("-y"), packages.to_vec()].concat(),
+            PackageOp::UpgradeAll => vec!["upgrade".into(), "all".into(), "-y".into()],
+            PackageOp::ListUpdates => vec!["outdated".into()],
+        },
+        other => anyhow::bail!("unsupported package manager: {other}"),
+    };
+
+    Ok((manager.to_string(), args))
+}
+
+/// Parse a `PackageOp::ListUpdates` run's stdout into a structured list,
+/// per the output format each backend actually produces.
+fn parse_list_updates(manager: &str, stdout: &str) -> Vec<PackageUpdate> {
+    match manager {
+        "apt-get" | "apt" => stdout
+            .lines()
+            .skip(1) // "Listing..." header
+            .filter_map(|line| {
+                // "pkgname/suite version arch [upgradable from: old]"
+                let name = line.split('/').next()?.trim();
+                if name.is_empty() {
+                    return None;
+                }
+                let available_version = line.split_whitespace().nth(1).map(str::to_string);
+                let current_version = line
+                    .split("upgradable from:")
+                    .nth(1)
+                    .map(|v| v.trim_end_matches(']').trim().to_string());
+                Some(PackageUpdate {
+                    name: name.to_string(),
+                    current_version,
+                    available_version,
+                })
+            })
+            .collect(),
+        "dnf" | "yum" => stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with("Last metadata"))
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let name = fields.next()?.split('.').next()?.to_string();
+                let available_version = fields.next().map(str::to_string);
+                Some(PackageUpdate { name, current_version: None, available_version })
+            })
+            .collect(),
+        "zypper" => stdout
+            .lines()
+            .filter(|line| line.starts_with('v') || line.contains('|'))
+            .filter_map(|line| {
+                // "v | repo | name | current | available | arch"
+                let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+                if fields.len() < 5 {
+                    return None;
+                }
+                Some(PackageUpdate {
+                    name: fields[2].to_string(),
+                    current_version: Some(fields[3].to_string()),
+                    available_version: Some(fields[4].to_string()),
+                })
+            })
+            .collect(),
+        "pacman" => stdout
+            .lines()
+            .filter_map(|line| {
+                // "name current_version -> available_version"
+                let mut parts = line.split_whitespace();
+                let name = parts.next()?.to_string();
+                let current_version = parts.next().map(str::to_string);
+                let available_version = line.split("->").nth(1).map(|v| v.trim().to_string());
+                Some(PackageUpdate { name, current_version, available_version })
+            })
+            .collect(),
+        "winget" => stdout
+            .lines()
+            .skip_while(|line| !line.starts_with("Name"))
+            .skip(2) // header + separator row
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 3 {
+                    return None;
+                }
+                Some(PackageUpdate {
+                    name: fields[0].to_string(),
+                    current_version: Some(fields[fields.len() - 2].to_string()),
+                    available_version: Some(fields[fields.len() - 1].to_string()),
+                })
+            })
+            .collect(),
+        "choco" => stdout
+            .lines()
+            .filter(|line| line.contains('|'))
+            .filter_map(|line| {
+                // "name|current|available|pinned"
+                let fields: Vec<&str> = line.split('|').collect();
+                if fields.len() < 3 {
+                    return None;
+                }
+                Some(PackageUpdate {
+                    name: fields[0].to_string(),
+                    current_version: Some(fields[1].to_string()),
+                    available_version: Some(fields[2].to_string()),
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Run a single command spec, streaming stdout/stderr to the server as the
+/// child produces it (see `pump_output`) instead of buffering it all until
+/// exit, and enforcing [`COMMAND_EXEC_TIMEOUT_SECS`] as the run's wall-clock
+/// budget. `cancel` is flipped by `action::spawn_cancel_watcher` the moment
+/// the server reports the action canceled — checked alongside the timeout
+/// so a long-running child gets killed mid-flight instead of only ever
+/// being abandoned at `COMMAND_EXEC_TIMEOUT_SECS`.
+pub async fn execute_command(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    cmd: ServerCommand,
+    cancel: Arc<std::sync::atomic::AtomicBool>,
+) -> Result<ExecutionResult> {
     let id = cmd.id.clone();
 
+    let mut package_manager_and_op: Option<(String, PackageOp)> = None;
+
     // Prepare the command text
     let (program, args): (String, Vec<String>) = match cmd.spec {
         CommandSpec::Shell { command, .. } => {
@@ -37,50 +271,454 @@ pub async fn execute_command(cmd: ServerCommand) -> Result<ExecutionResult> {
             }
             (all_args.remove(0), all_args)
         }
+        CommandSpec::Package { manager, operation, packages, assume_yes } => {
+            let manager = match manager {
+                Some(m) => m,
+                None => detect_package_manager()?,
+            };
+            package_manager_and_op = Some((manager.clone(), operation));
+            package_command(&manager, operation, &packages, assume_yes)?
+        }
+        CommandSpec::Pty { .. } => {
+            // `execute_action` dispatches `CommandSpec::Pty` straight to
+            // `run_pty_session` and returns before this function is ever
+            // called — reaching here means that branch was bypassed.
+            anyhow::bail!("CommandSpec::Pty must be dispatched via run_pty_session, not execute_command");
+        }
+        CommandSpec::Watch { .. } => {
+            // Likewise dispatched straight to `run_watch_session` by
+            // `execute_action`.
+            anyhow::bail!("CommandSpec::Watch must be dispatched via run_watch_session, not execute_command");
+        }
     };
 
-    let run = task::spawn_blocking(move || {
-        Command::new(program)
-            .args(&args)
-            .output()
-            .map_err(|e| format!("failed spawn: {}", e))
-    })
-    .await?;
+    let started = Instant::now();
+
+    let mut child = tokio::process::Command::new(program)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed spawn: {}", e))?;
+
+    let stdout_pipe = child.stdout.take().expect("piped stdout");
+    let stderr_pipe = child.stderr.take().expect("piped stderr");
+
+    let seq = Arc::new(AtomicU64::new(0));
+    let stdout_acc = Arc::new(Mutex::new(String::new()));
+    let stderr_acc = Arc::new(Mutex::new(String::new()));
 
-    let output = match run {
-        Ok(o) => o,
-        Err(e) => {
-            return Err(anyhow::anyhow!("Execution failed: {}", e));
+    let stdout_task = tokio::spawn(pump_output(
+        stdout_pipe,
+        OutputStream::Stdout,
+        id.clone(),
+        client.clone(),
+        server_url.to_string(),
+        device_id.to_string(),
+        seq.clone(),
+        stdout_acc.clone(),
+    ));
+    let stderr_task = tokio::spawn(pump_output(
+        stderr_pipe,
+        OutputStream::Stderr,
+        id.clone(),
+        client.clone(),
+        server_url.to_string(),
+        device_id.to_string(),
+        seq.clone(),
+        stderr_acc.clone(),
+    ));
+
+    // Polls in short slices rather than one long `timeout` so the loop gets
+    // a chance to notice `cancel` going true partway through the run, not
+    // just at the wall-clock deadline.
+    const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+    let deadline = Instant::now() + Duration::from_secs(COMMAND_EXEC_TIMEOUT_SECS);
+
+    let (exit_code, state) = loop {
+        if cancel.load(Ordering::SeqCst) {
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+            break (-1, CommandState::Canceled);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            let _ = child.start_kill();
+            break (-1, CommandState::TimedOut);
+        }
+
+        match timeout(remaining.min(CANCEL_CHECK_INTERVAL), child.wait()).await {
+            Ok(Ok(status)) => {
+                break (
+                    status.code().unwrap_or(-1),
+                    if status.success() { CommandState::Completed } else { CommandState::Failed },
+                );
+            }
+            Ok(Err(e)) => return Err(anyhow::anyhow!("wait failed: {}", e)),
+            Err(_) => continue, // this slice elapsed; loop back to re-check cancel/deadline
         }
     };
 
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    let code = output.status.code().unwrap_or(-1);
+    // Let the pump tasks drain whatever they already read before pulling
+    // the accumulated buffers back out below.
+    let _ = tokio::join!(stdout_task, stderr_task);
+
+    let duration_secs = started.elapsed().as_secs_f64();
+    let stdout = stdout_acc.lock().await.clone();
+    let stderr = stderr_acc.lock().await.clone();
+
+    // `check-update`/`zypper list-updates` exit non-zero when updates exist
+    // — that's not a failure, so parse before the caller judges success
+    // purely off `exit_code`.
+    let package_updates = match &package_manager_and_op {
+        Some((manager, PackageOp::ListUpdates)) => Some(parse_list_updates(manager, &stdout)),
+        _ => None,
+    };
+    // Exit code 100 is how these backends say "updates are available".
+    let state = match &package_manager_and_op {
+        Some((manager, PackageOp::ListUpdates))
+            if exit_code == 100 && matches!(manager.as_str(), "dnf" | "yum" | "zypper") =>
+        {
+            CommandState::Completed
+        }
+        _ => state,
+    };
 
     Ok(ExecutionResult {
         id,
         stdout,
         stderr,
-        exit_code: code,
+        exit_code,
+        duration_secs,
+        state,
+        package_updates,
     })
 }
 
-/// Post execution result to server
-pub async fn post_command_result(
+/// Drain one pipe as the child produces it, appending each read to `acc` (so
+/// the final `ExecutionResult` still carries the full text) and handing it
+/// off to `spool::queue_and_send_output` so a frame survives a failed send
+/// instead of just being logged and dropped.
+#[allow(clippy::too_many_arguments)]
+async fn pump_output(
+    mut pipe: impl tokio::io::AsyncRead + Unpin,
+    stream: OutputStream,
+    cmd_id: String,
+    client: Client,
+    server_url: String,
+    device_id: String,
+    seq: Arc<AtomicU64>,
+    acc: Arc<Mutex<String>>,
+) {
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match pipe.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(_) => break,
+        };
+
+        let chunk = String::from_utf8_lossy(&buf[..n]).to_string();
+        acc.lock().await.push_str(&chunk);
+
+        let frame = OutputFrame {
+            seq: seq.fetch_add(1, Ordering::SeqCst),
+            stream,
+            data: chunk,
+        };
+        crate::spool::queue_and_send_output(&client, &server_url, &device_id, cmd_id.clone(), frame).await;
+    }
+}
+
+/// Post a single streamed output chunk for a command that's still running.
+/// Returns whether the server accepted it (2xx) — used by `spool` to decide
+/// whether the durably-queued copy can be removed.
+pub(crate) async fn post_output_frame(
     client: &Client,
     server_url: &str,
+    device_id: &str,
     cmd_id: &str,
-    result: &CommandResult,
+    frame: &OutputFrame,
+) -> Result<bool> {
+    let url = format!(
+        "{}/api/devices/{}/commands/{}/output",
+        server_url.trim_end_matches('/'),
+        device_id,
+        cmd_id
+    );
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.post(&url).json(frame);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
+    Ok(resp.status().is_success())
+}
+
+#[cfg(unix)]
+fn default_shell() -> String {
+    std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+}
+
+#[cfg(windows)]
+fn default_shell() -> String {
+    "cmd.exe".to_string()
+}
+
+/// Run an interactive PTY session for a `CommandSpec::Pty` action: allocate
+/// a pty (see `pty_shell::ShellSession`), forward its output to the exact
+/// `/shell/<session>/frame` endpoint `routes/shell.rs` expects, and poll
+/// `/shell/<session>/control/poll` for operator stdin/resize/kill frames,
+/// until the shell exits or the action itself is canceled/expires — the
+/// same lifecycle check every other command gets from
+/// `action::is_canceled`/`action::is_expired`, rather than a separate
+/// timeout.
+pub async fn run_pty_session(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    cmd: &ServerCommand,
+    shell: Option<String>,
+) -> Result<ExecutionResult> {
+    let shell = shell.unwrap_or_else(default_shell);
+    let started = Instant::now();
+
+    let session = ShellSession::spawn(cmd.id.clone(), &shell)
+        .map_err(|e| anyhow::anyhow!("failed to open pty: {:?}", e))?;
+
+    let exit_code = loop {
+        if crate::action::is_expired(cmd)
+            || crate::action::is_canceled(client, server_url, device_id, &cmd.id).await
+        {
+            let _ = session.send_control(ShellControl::Kill { session_id: cmd.id.clone() });
+        }
+
+        for ctrl in poll_shell_control(client, server_url, device_id, &cmd.id).await? {
+            let _ = session.send_control(ctrl);
+        }
+
+        let mut exited = None;
+        for frame in session.try_recv_frames() {
+            if let ShellFrame::Exit { code, .. } = &frame {
+                exited = Some(*code);
+            }
+            post_shell_frame(client, server_url, device_id, &cmd.id, &frame).await?;
+        }
+
+        if let Some(code) = exited {
+            break code;
+        }
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    };
+
+    Ok(ExecutionResult {
+        id: cmd.id.clone(),
+        stdout: String::new(),
+        stderr: String::new(),
+        exit_code,
+        duration_secs: started.elapsed().as_secs_f64(),
+        state: if exit_code == 0 { CommandState::Completed } else { CommandState::Failed },
+        package_updates: None,
+    })
+}
+
+/// Fetch any control frames (stdin/resize/kill) an operator queued for this
+/// session since the last poll.
+async fn poll_shell_control(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    session_id: &str,
+) -> Result<Vec<ShellControl>> {
+    let url = format!(
+        "{}/api/devices/{}/shell/{}/control/poll",
+        server_url.trim_end_matches('/'),
+        device_id,
+        session_id
+    );
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.get(&url);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
+    if !resp.status().is_success() {
+        return Ok(vec![]);
+    }
+    Ok(resp.json().await.unwrap_or_default())
+}
+
+/// Post a single output/exit frame for a running session.
+async fn post_shell_frame(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    session_id: &str,
+    frame: &ShellFrame,
+) -> Result<()> {
+    let url = format!(
+        "{}/api/devices/{}/shell/{}/frame",
+        server_url.trim_end_matches('/'),
+        device_id,
+        session_id
+    );
+    crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.post(&url).json(frame);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
+    Ok(())
+}
+
+/// Watch the requested paths and POST debounced change batches until
+/// `routes/watch.rs`'s status route reports the action no longer active
+/// (canceled/expired) or `watcher::WatchSession` itself errors out.
+/// `is_expired` is checked locally first on each tick so an already-past
+/// deadline doesn't need a round trip to learn what it already knows.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_watch_session(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    cmd: &ServerCommand,
+    paths: Vec<String>,
+    recursive: Option<bool>,
+    debounce_ms: Option<u64>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+) -> Result<()> {
+    use crate::watcher::{WatchSession, WatchSpec};
+
+    if paths.is_empty() {
+        anyhow::bail!("watch command {} named no paths; nothing to do", cmd.id);
+    }
+
+    let spec = WatchSpec {
+        paths,
+        recursive: recursive.unwrap_or(true),
+        debounce_ms: debounce_ms.unwrap_or(500),
+        include,
+        exclude,
+    };
+
+    let session = WatchSession::spawn(cmd.id.clone(), spec)
+        .map_err(|e| anyhow::anyhow!("failed to start watch session: {:?}", e))?;
+
+    let mut last_status_check = Instant::now();
+    let status_check_interval = Duration::from_secs(10);
+
+    loop {
+        for batch in session.try_recv_batches() {
+            post_watch_batch(client, server_url, device_id, &cmd.id, &batch).await?;
+        }
+
+        if crate::action::is_expired(cmd) {
+            session.stop();
+            break;
+        }
+
+        if last_status_check.elapsed() >= status_check_interval {
+            last_status_check = Instant::now();
+            if !watch_status_active(client, server_url, device_id, &cmd.id).await {
+                session.stop();
+                break;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+
+    Ok(())
+}
+
+/// Post a debounced batch of filesystem changes for a running watch.
+async fn post_watch_batch(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    action_id: &str,
+    batch: &[crate::watcher::FileChangeEvent],
 ) -> Result<()> {
+    let url = format!(
+        "{}/api/devices/{}/watch/{}/events",
+        server_url.trim_end_matches('/'),
+        device_id,
+        action_id
+    );
+    crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.post(&url).json(batch);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
+    Ok(())
+}
+
+/// Poll whether the watch action is still active (not canceled/expired).
+/// Best-effort, like `action::is_canceled`: a poll failure is treated as
+/// "still active" rather than tearing down the watch on a transient blip.
+async fn watch_status_active(client: &Client, server_url: &str, device_id: &str, action_id: &str) -> bool {
+    let url = format!(
+        "{}/api/devices/{}/watch/{}/status",
+        server_url.trim_end_matches('/'),
+        device_id,
+        action_id
+    );
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.get(&url);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await;
+
+    match resp {
+        Ok(r) if r.status().is_success() => r
+            .json::<serde_json::Value>()
+            .await
+            .ok()
+            .and_then(|body| body.get("active").and_then(|v| v.as_bool()))
+            .unwrap_or(true),
+        _ => true,
+    }
+}
+
+/// Post execution result to server. Returns whether the server accepted it
+/// (2xx) — used by `spool` to decide whether the durably-queued copy can be
+/// removed.
+pub(crate) async fn post_command_result(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    cmd_id: &str,
+    result: &CommandResult,
+) -> Result<bool> {
     let url = format!("{}/api/commands/{}/result", server_url, cmd_id);
 
-    // Explicit type annotation to satisfy Rust
-    let resp: reqwest::Response = client.post(&url).json(result).send().await?;
+    let resp = crate::device::request_with_auth(client, server_url, device_id, |c, token| {
+        let mut req = c.post(&url).json(result);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
 
     if !resp.status().is_success() {
         log::warn!("Server rejected command result {}: {}", cmd_id, resp.status());
     }
 
-    Ok(())
+    Ok(resp.status().is_success())
 }
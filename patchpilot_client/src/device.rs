@@ -1,25 +1,108 @@
 use anyhow::{Context, Result, anyhow};
 use reqwest::Client;
 use serde_json::{json, Value};
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use tokio::time::{sleep, Duration};
 use crate::system_info::{
-    SystemInfo, SystemInfoService, get_local_device_id, write_local_device_id, get_device_info_basic
+    SystemInfo, SystemInfoService, ProbeResult, get_local_device_id, write_local_device_id, get_device_info_basic,
+    get_local_refresh_token, write_local_refresh_token, read_probe_targets,
 };
 use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
 use std::time::Instant;
+use lazy_static::lazy_static;
 
 pub const ADOPTION_CHECK_INTERVAL: i64 = 10;
 
+/// Protocol version this client speaks, sent on every registration and
+/// heartbeat so the server can flag stale agents via `protocol_outdated`
+/// rather than silently dispatching them work they can't run.
+pub const PROTOCOL_VERSION: i32 = 2;
+
+/// Capabilities this client implements, matched against `cmd.spec` in
+/// `action::execute_action`. Keep in sync with that dispatch.
+pub const CAPABILITIES: &[&str] = &["pty", "log_tail", "self_update", "stream"];
+
+lazy_static! {
+    /// The capability set the server most recently echoed back as
+    /// actually negotiated (the intersection of what we advertised and
+    /// what it understands). Starts empty, so any command requiring a
+    /// capability is rejected until the first successful registration or
+    /// heartbeat populates it.
+    static ref NEGOTIATED_CAPABILITIES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    /// The device access token most recently exchanged for our refresh
+    /// token (see `exchange_device_token`). Starts empty: a brand-new,
+    /// not-yet-approved device has no refresh token yet, so it heartbeats
+    /// without a bearer header until the server hands one out.
+    static ref DEVICE_ACCESS_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Record the capability list the server returned in a registration or
+/// heartbeat response.
+fn store_negotiated_capabilities(response: &Value) {
+    if let Some(caps) = response.get("capabilities").and_then(|v| v.as_array()) {
+        let caps: Vec<String> = caps
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect();
+        *NEGOTIATED_CAPABILITIES.lock().unwrap() = caps;
+    }
+}
+
+/// Whether the server has confirmed it can handle `cap` for this agent.
+/// Used by `action::execute_action` to reject commands that need a
+/// capability that wasn't negotiated, instead of attempting them and
+/// failing cryptically.
+pub fn has_negotiated_capability(cap: &str) -> bool {
+    NEGOTIATED_CAPABILITIES.lock().unwrap().iter().any(|c| c == cap)
+}
+
 // Helper: measure TCP ping (ms) to host:port
 fn measure_tcp_ping(host: &str, port: u16, timeout_ms: i64) -> Option<f32> {
     let addr = format!("{}:{}", host, port);
     let addr = addr.to_socket_addrs().ok()?.next()?;
-    let start = Instant::Utc::now();
+    let start = Instant::now();
     let _ = TcpStream::connect_timeout(&addr, Duration::from_millis(timeout_ms)).ok()?;
     Some(start.elapsed().as_secs_f32() * 1000.0)
 }
 
+/// Bound on each individual probe's `connect_timeout`, so one unreachable
+/// target can't stall a heartbeat past this.
+const PROBE_TIMEOUT_MS: i64 = 2000;
+
+/// Measure every configured probe target concurrently (each one's own
+/// `measure_tcp_ping` call runs on a blocking-pool thread so a slow/hanging
+/// target doesn't block the others), returning one result per target.
+async fn measure_probe_targets(targets: &[String]) -> Vec<ProbeResult> {
+    let handles: Vec<_> = targets
+        .iter()
+        .cloned()
+        .map(|target| tokio::task::spawn_blocking(move || probe_one(target)))
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        if let Ok(result) = handle.await {
+            results.push(result);
+        }
+    }
+    results
+}
+
+fn probe_one(target: String) -> ProbeResult {
+    match target
+        .rsplit_once(':')
+        .and_then(|(host, port)| port.parse::<u16>().ok().map(|port| (host.to_string(), port)))
+    {
+        Some((host, port)) => {
+            let ping_ms = measure_tcp_ping(&host, port, PROBE_TIMEOUT_MS);
+            ProbeResult { up: ping_ms.is_some(), ping_ms, target }
+        }
+        None => ProbeResult { target, ping_ms: None, up: false },
+    }
+}
+
 // Register the device with the server
 pub async fn register_device(
     client: &Client,
@@ -37,10 +120,12 @@ pub async fn register_device(
         "device_id": device_id,
         "system_info": sys_info,
         "device_type": device_type,
-        "device_model": device_model
+        "device_model": device_model,
+        "protocol_version": PROTOCOL_VERSION,
+        "capabilities": CAPABILITIES,
     });
 
-    let url = format!("{}/api/register", server_url);
+    let url = format!("{}/api/v1/register", server_url);
     let response = client
         .post(&url)
         .json(&payload)
@@ -58,14 +143,207 @@ pub async fn register_device(
     let parsed: Value =
         serde_json::from_str(&body).context("Server returned invalid JSON")?;
 
+    store_negotiated_capabilities(&parsed);
+
     if let Some(did) = parsed.get("device_id").and_then(|v| v.as_str()) {
         write_local_device_id(did)?;
+
+        if let Err(e) = perform_auth_request_handshake(client, server_url, did, &None).await {
+            log::warn!(
+                "Device-approval handshake failed, falling back to heartbeat-based adoption: {}",
+                e
+            );
+        }
+
         return Ok(did.to_string());
     }
 
     anyhow::bail!("Server did not return device_id");
 }
 
+/// Fetch a single-use nonce from the server to bind into this heartbeat's
+/// signature, so a captured heartbeat can't be replayed.
+async fn fetch_heartbeat_nonce(client: &Client, server_url: &str) -> Result<String> {
+    let resp = client
+        .get(format!("{}/api/v1/nonce", server_url))
+        .send()
+        .await
+        .context("Fetching heartbeat nonce failed")?;
+
+    let v: Value = resp.json().await.context("Parsing nonce response")?;
+    v.get("nonce")
+        .and_then(|n| n.as_str())
+        .map(|n| n.to_string())
+        .ok_or_else(|| anyhow!("Server did not return a nonce"))
+}
+
+/// Exchange a persisted refresh token for a fresh access token, rotating
+/// the refresh token in the same call (the server invalidates the old one
+/// the instant it issues a new one). Persists the rotated refresh token
+/// and caches the access token in memory for `send_heartbeat` to attach.
+async fn exchange_device_token(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    refresh_token: &str,
+) -> Result<String> {
+    let payload = json!({
+        "device_id": device_id,
+        "refresh_token": refresh_token,
+    });
+
+    let resp = client
+        .post(format!("{}/api/v1/token", server_url))
+        .json(&payload)
+        .send()
+        .await
+        .context("Device token exchange request failed")?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Device token exchange rejected: {}", resp.status());
+    }
+
+    let v: Value = resp.json().await.context("Parsing token exchange response")?;
+    let access_token = v
+        .get("access_token")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| anyhow!("Token exchange response missing access_token"))?
+        .to_string();
+
+    if let Some(new_refresh_token) = v.get("refresh_token").and_then(|t| t.as_str()) {
+        write_local_refresh_token(new_refresh_token)?;
+    }
+
+    *DEVICE_ACCESS_TOKEN.lock().unwrap() = Some(access_token.clone());
+    Ok(access_token)
+}
+
+/// The cached device access token, if we're holding one in memory.
+fn cached_access_token() -> Option<String> {
+    DEVICE_ACCESS_TOKEN.lock().unwrap().clone()
+}
+
+/// A usable access token for attaching to a request: the cached one if we
+/// have it, otherwise exchanged fresh from the persisted refresh token.
+/// `None` means we have no refresh token yet (not approved) or the
+/// exchange itself failed — callers send unauthenticated in that case, the
+/// same as before this credential subsystem existed.
+pub(crate) async fn ensure_access_token(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+) -> Option<String> {
+    if let Some(token) = cached_access_token() {
+        return Some(token);
+    }
+    let refresh_token = get_local_refresh_token()?;
+    exchange_device_token(client, server_url, device_id, &refresh_token).await.ok()
+}
+
+/// Force a fresh access token exchange, e.g. after a request comes back
+/// `401` on the cached one.
+async fn refresh_access_token(client: &Client, server_url: &str, device_id: &str) -> Option<String> {
+    let refresh_token = get_local_refresh_token()?;
+    exchange_device_token(client, server_url, device_id, &refresh_token).await.ok()
+}
+
+/// Attach a device access token to a request built by `build`, retrying
+/// once with a freshly exchanged token if the server rejects the cached
+/// one with `401`. Used by every request the command-poll channel makes
+/// (`action::poll_for_commands_once`, `command::post_command_result`,
+/// `command::post_output_frame`) so a flaky/expired token doesn't need
+/// each call site to reimplement the same retry.
+pub(crate) async fn request_with_auth(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    mut build: impl FnMut(&Client, Option<&str>) -> reqwest::RequestBuilder,
+) -> reqwest::Result<reqwest::Response> {
+    let token = ensure_access_token(client, server_url, device_id).await;
+    let resp = build(client, token.as_deref()).send().await?;
+
+    if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+        if let Some(fresh) = refresh_access_token(client, server_url, device_id).await {
+            return build(client, Some(&fresh)).send().await;
+        }
+    }
+
+    Ok(resp)
+}
+
+/// Client certificate + key (PEM, concatenated — as `reqwest::Identity`
+/// expects) this device authenticates to the server with at the TLS
+/// layer, on top of the bearer device token every request also carries.
+const CLIENT_CERT_FILENAME: &str = "client.pem";
+/// Optional pinned CA certificate (PEM). When present, the server's
+/// certificate is verified against this instead of the system root store.
+const PINNED_CA_FILENAME: &str = "pinned_ca.pem";
+/// Marker file whose mere presence (contents are ignored) means
+/// `build_client` must refuse to hand back a client at all if no client
+/// certificate is configured — adoption must not proceed over an
+/// unauthenticated channel.
+const REQUIRE_MTLS_FILENAME: &str = "require_mtls";
+
+/// Typed failure building the client every request to the server goes
+/// through (see `build_client`).
+#[derive(Debug, thiserror::Error)]
+pub enum ClientBuildError {
+    #[error("failed to read client certificate from {0:?}: {1}")]
+    ReadCert(PathBuf, std::io::Error),
+    #[error("failed to read pinned CA certificate from {0:?}: {1}")]
+    ReadCa(PathBuf, std::io::Error),
+    #[error("invalid client certificate/key: {0}")]
+    Identity(reqwest::Error),
+    #[error("invalid pinned CA certificate: {0}")]
+    Ca(reqwest::Error),
+    #[error("mutual TLS is required (see {0:?}) but no client certificate was found at {1:?}")]
+    MissingRequiredCert(PathBuf, PathBuf),
+    #[error("failed to build HTTP client: {0}")]
+    Build(reqwest::Error),
+}
+
+/// Build the `reqwest::Client` used for every request this agent makes —
+/// the heartbeat/adoption loop, the command-poll channel, and the relay
+/// listener all share one. Loads a client identity from
+/// `<base_dir>/client.pem` if present, so the server can authenticate this
+/// device at the TLS layer in addition to the bearer device token attached
+/// per-request (see `request_with_auth`), and an optional pinned CA from
+/// `<base_dir>/pinned_ca.pem` so a rogue CA in the system trust store
+/// can't spoof the server.
+pub fn build_client() -> Result<Client, ClientBuildError> {
+    let base = PathBuf::from(crate::get_base_dir());
+    let mut builder = Client::builder();
+
+    let cert_path = base.join(CLIENT_CERT_FILENAME);
+    match std::fs::read(&cert_path) {
+        Ok(pem) => {
+            let identity = reqwest::Identity::from_pem(&pem).map_err(ClientBuildError::Identity)?;
+            builder = builder.identity(identity);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if base.join(REQUIRE_MTLS_FILENAME).exists() {
+                return Err(ClientBuildError::MissingRequiredCert(
+                    base.join(REQUIRE_MTLS_FILENAME),
+                    cert_path,
+                ));
+            }
+        }
+        Err(source) => return Err(ClientBuildError::ReadCert(cert_path, source)),
+    }
+
+    let ca_path = base.join(PINNED_CA_FILENAME);
+    match std::fs::read(&ca_path) {
+        Ok(pem) => {
+            let ca = reqwest::Certificate::from_pem(&pem).map_err(ClientBuildError::Ca)?;
+            builder = builder.add_root_certificate(ca);
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(source) => return Err(ClientBuildError::ReadCa(ca_path, source)),
+    }
+
+    builder.build().map_err(ClientBuildError::Build)
+}
+
 // Send heartbeat to server
 pub async fn send_heartbeat(
     client: &Client,
@@ -75,30 +353,205 @@ pub async fn send_heartbeat(
     device_model: &str,
     system_info_service: &Arc<SystemInfoService>,
 ) -> Result<Value> {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
+    use sha2::{Digest, Sha256};
+
     let mut sys_info = system_info_service.get_system_info_async().await.unwrap_or_default();
 
+    // Network-path telemetry: how far away does the server itself look
+    // right now, and are the operator's configured probe targets reachable?
+    sys_info.server_latency_ms = reqwest::Url::parse(server_url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| (h.to_string(), u.port_or_known_default().unwrap_or(443))))
+        .and_then(|(host, port)| measure_tcp_ping(&host, port, PROBE_TIMEOUT_MS));
+    sys_info.probe_results = measure_probe_targets(&read_probe_targets()).await;
+
+    let nonce = fetch_heartbeat_nonce(client, server_url).await?;
+    let signing_key = crate::system_info::get_or_create_signing_key()?;
+    let public_key_b64 =
+        base64::engine::general_purpose::STANDARD.encode(signing_key.verifying_key().to_bytes());
+
+    // Canonicalize via `Value` before hashing so the server, which only
+    // ever sees `system_info` after parsing it into a `Value` itself, hashes
+    // the exact same bytes we sign here.
+    let system_info_value = serde_json::to_value(&sys_info).context("Serializing system_info")?;
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&system_info_value).unwrap_or_default());
+    let system_info_hash = hasher.finalize();
+
+    let mut message = Vec::new();
+    message.extend_from_slice(nonce.as_bytes());
+    message.extend_from_slice(device_id.as_bytes());
+    message.extend_from_slice(&system_info_hash);
+    let signature = signing_key.sign(&message);
+    let signature_b64 = base64::engine::general_purpose::STANDARD.encode(signature.to_bytes());
+
     let payload = json!({
         "device_id": device_id,
-        "system_info": sys_info,
+        "system_info": system_info_value,
         "device_type": device_type,
-        "device_model": device_model
+        "device_model": device_model,
+        "protocol_version": PROTOCOL_VERSION,
+        "capabilities": CAPABILITIES,
+        "nonce": nonce,
+        "signature": signature_b64,
+        "public_key": public_key_b64,
     });
 
-    let resp = client
-        .post(format!("{}/api/devices/heartbeat", server_url))
-        .json(&payload)
-        .send()
+    // Once we've been issued a refresh token, every heartbeat must carry a
+    // device access token exchanged from it — attach one if we're holding
+    // one in memory, exchanging fresh on startup if we aren't.
+    let mut access_token = DEVICE_ACCESS_TOKEN.lock().unwrap().clone();
+    if access_token.is_none() {
+        if let Some(refresh_token) = get_local_refresh_token() {
+            access_token = exchange_device_token(client, server_url, device_id, &refresh_token)
+                .await
+                .ok();
+        }
+    }
+
+    let send = |access_token: Option<&str>| {
+        let mut req = client
+            .post(format!("{}/api/v1/devices/heartbeat", server_url))
+            .json(&payload);
+        if let Some(token) = access_token {
+            req = req.bearer_auth(token);
+        }
+        req.send()
+    };
+
+    let mut resp = send(access_token.as_deref())
         .await
         .context("Heartbeat request failed")?;
 
+    // The access token is short-lived; if it's expired or missing, refresh
+    // it from the persisted refresh token and retry once.
+    if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+        if let Some(refresh_token) = get_local_refresh_token() {
+            if let Ok(fresh_token) =
+                exchange_device_token(client, server_url, device_id, &refresh_token).await
+            {
+                resp = send(Some(&fresh_token))
+                    .await
+                    .context("Heartbeat retry request failed")?;
+            }
+        }
+    }
+
     if !resp.status().is_success() {
         anyhow::bail!("Heartbeat request rejected: {}", resp.status());
     }
 
     let v = resp.json::<Value>().await.context("Parsing heartbeat response JSON")?;
+    store_negotiated_capabilities(&v);
+
+    if let Some(refresh_token) = v.get("refresh_token").and_then(|t| t.as_str()) {
+        write_local_refresh_token(refresh_token)?;
+    }
+
     Ok(v)
 }
 
+/// Device-approval handshake: generate an ephemeral keypair, submit the
+/// public key and a short access code the operator can visually confirm,
+/// then poll until approved. On approval the server hands back the
+/// adoption secret sealed to our public key — only decryptable here,
+/// proving this agent (not just some heartbeat claiming its device_id) was
+/// physically present when the operator approved it. Best-effort: if this
+/// fails or the operator only ever uses the older `approve_device`
+/// dashboard action, the caller falls back to the existing heartbeat-based
+/// `adopted` check.
+async fn perform_auth_request_handshake(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    running_flag: &Option<Arc<AtomicBool>>,
+) -> Result<()> {
+    use base64::Engine;
+    use crypto_box::SecretKey;
+    use rand::rngs::OsRng;
+    use rand::Rng;
+
+    let secret_key = SecretKey::generate(&mut OsRng);
+    let public_key = secret_key.public_key();
+    let public_key_b64 = base64::engine::general_purpose::STANDARD.encode(public_key.as_bytes());
+
+    let mut rng = rand::thread_rng();
+    let access_code: String = (0..6)
+        .map(|_| std::char::from_digit(rng.gen_range(0..10), 10).unwrap())
+        .collect();
+    log::info!(
+        "Device approval access code: {} — confirm this matches what the operator sees before approving",
+        access_code
+    );
+
+    let resp = client
+        .post(format!("{}/api/v1/auth-request", server_url))
+        .json(&json!({
+            "device_id": device_id,
+            "public_key": public_key_b64,
+            "access_code": access_code,
+        }))
+        .send()
+        .await
+        .context("Failed to submit auth request")?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Auth request submission rejected: {}", resp.status());
+    }
+
+    let request_id = resp
+        .json::<Value>()
+        .await
+        .context("Parsing auth-request response")?
+        .get("request_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("Server did not return a request_id"))?
+        .to_string();
+
+    loop {
+        if let Some(flag) = running_flag {
+            if !flag.load(Ordering::SeqCst) {
+                anyhow::bail!("Service stopping during device approval wait");
+            }
+        }
+
+        let poll_resp = client
+            .get(format!("{}/api/v1/auth-request/{}", server_url, request_id))
+            .send()
+            .await
+            .context("Polling auth request failed")?;
+
+        let status_json: Value = poll_resp
+            .json()
+            .await
+            .context("Parsing auth-request poll response")?;
+
+        match status_json.get("status").and_then(|v| v.as_str()) {
+            Some("approved") => {
+                let secret_enc = status_json
+                    .get("secret_enc")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("Approved response missing secret_enc"))?;
+                let ciphertext = base64::engine::general_purpose::STANDARD
+                    .decode(secret_enc)
+                    .context("Decoding adoption secret")?;
+                crypto_box::seal_open(&secret_key, &public_key, &ciphertext)
+                    .map_err(|_| anyhow!("Failed to decrypt adoption secret — wrong keypair?"))?;
+                log::info!("Device approval confirmed; adoption secret verified.");
+                return Ok(());
+            }
+            Some("rejected") => {
+                anyhow::bail!("Device approval request was rejected by an operator");
+            }
+            _ => {
+                sleep(Duration::from_secs(ADOPTION_CHECK_INTERVAL)).await;
+            }
+        }
+    }
+}
+
 // Run adoption & update loop
 pub async fn run_adoption_and_update_loop(
     client: &Client,
@@ -143,6 +596,9 @@ pub async fn run_adoption_and_update_loop(
 
         match send_heartbeat(client, server_url, &device_id, &device_type, &device_model, &system_info_service).await {
             Ok(v) => {
+                if v.get("protocol_outdated").and_then(|x| x.as_bool()).unwrap_or(false) {
+                    log::warn!("Server reports this client's protocol version ({}) is outdated; some actions may not be dispatched.", PROTOCOL_VERSION);
+                }
                 let adopted = v.get("adopted").and_then(|x| x.as_bool()).unwrap_or(false);
                 if adopted {
                     break;
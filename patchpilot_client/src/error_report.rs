@@ -0,0 +1,81 @@
+//! Central channel for command execution/post failures that used to be
+//! dropped after a single `log::warn!` (see `action::execute_action`).
+//! `service.rs` creates the channel and hands the sending half to
+//! `action_loop`, which passes it on to `execute_action`;
+//! `run_report_task` drains the receiving half for the
+//! life of the process, POSTing each report to
+//! `/devices/{id}/commands/{cmd}/errors` and retrying with backoff on
+//! anything short of a 2xx, so a report survives a server blip the same
+//! way `spool::run_flush_task` keeps a command result alive.
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::Duration;
+
+const INITIAL_REPORT_BACKOFF_SECS: u64 = 2;
+const MAX_REPORT_BACKOFF_SECS: u64 = 60;
+
+/// One execution or post failure worth recording in the server's
+/// `audit`/`history_log` tables.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    #[serde(skip)]
+    pub device_id: String,
+    #[serde(skip)]
+    pub cmd_id: String,
+    /// Short machine-readable stage the failure happened in, e.g.
+    /// `"execution"`, `"expired"`, `"canceled"`.
+    pub phase: String,
+    pub message: String,
+}
+
+pub type ErrorReportSender = UnboundedSender<ErrorReport>;
+
+/// Create the channel whose sending half `action_loop` wires into
+/// `execute_action`, keeping the receiving half for `run_report_task`.
+pub fn channel() -> (ErrorReportSender, UnboundedReceiver<ErrorReport>) {
+    mpsc::unbounded_channel()
+}
+
+/// Drain `rx` for as long as the process runs, retrying each report with
+/// capped exponential backoff instead of dropping it on the first failure.
+/// A report stuck retrying doesn't block later ones from queuing up behind
+/// it in the channel — it just delays their turn at the head of the line.
+pub async fn run_report_task(client: Client, server_url: String, mut rx: UnboundedReceiver<ErrorReport>) {
+    while let Some(report) = rx.recv().await {
+        let mut backoff_secs = INITIAL_REPORT_BACKOFF_SECS;
+        loop {
+            match post_report(&client, &server_url, &report).await {
+                Ok(true) => break,
+                Ok(false) => log::warn!(
+                    "Server rejected error report for {} ({}), retrying in {}s",
+                    report.cmd_id, report.phase, backoff_secs
+                ),
+                Err(e) => log::warn!(
+                    "Failed to send error report for {} ({}), retrying in {}s: {}",
+                    report.cmd_id, report.phase, backoff_secs, e
+                ),
+            }
+            tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+            backoff_secs = (backoff_secs * 2).min(MAX_REPORT_BACKOFF_SECS);
+        }
+    }
+}
+
+async fn post_report(client: &Client, server_url: &str, report: &ErrorReport) -> anyhow::Result<bool> {
+    let url = format!(
+        "{}/api/devices/{}/commands/{}/errors",
+        server_url.trim_end_matches('/'),
+        report.device_id,
+        report.cmd_id
+    );
+    let resp = crate::device::request_with_auth(client, server_url, &report.device_id, |c, token| {
+        let mut req = c.post(&url).json(report);
+        if let Some(t) = token {
+            req = req.bearer_auth(t);
+        }
+        req
+    })
+    .await?;
+    Ok(resp.status().is_success())
+}
@@ -0,0 +1,112 @@
+//! Poll-based tailer for the client's own `flexi_logger` output, used to
+//! back the dashboard's live log view without an inotify/kqueue dependency
+//! (see `service::init_logging` for the rotation policy this follows).
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Tracks an open log file and the byte offset already emitted, detecting
+/// `flexi_logger` rotation (size shrink, or — on Unix — inode change) and
+/// reopening the newest matching file from scratch when it happens.
+pub struct LogTailer {
+    log_dir: PathBuf,
+    basename: String,
+    current_path: Option<PathBuf>,
+    offset: u64,
+    #[cfg(unix)]
+    inode: Option<u64>,
+}
+
+impl LogTailer {
+    pub fn new(log_dir: PathBuf, basename: &str) -> Self {
+        Self {
+            log_dir,
+            basename: basename.to_string(),
+            current_path: None,
+            offset: 0,
+            #[cfg(unix)]
+            inode: None,
+        }
+    }
+
+    /// Most recently modified file matching `<basename>*.log` in the log
+    /// directory — this is the file flexi_logger is actively appending to.
+    fn find_active_file(&self) -> Option<PathBuf> {
+        let entries = std::fs::read_dir(&self.log_dir).ok()?;
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .map(|n| n.starts_with(&self.basename) && n.ends_with(".log"))
+                    .unwrap_or(false)
+            })
+            .max_by_key(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+    }
+
+    /// Read whatever's been appended since the last poll. Reopens the file
+    /// from the start if rotation is detected.
+    pub fn poll(&mut self) -> std::io::Result<String> {
+        let active = match self.find_active_file() {
+            Some(p) => p,
+            None => return Ok(String::new()),
+        };
+
+        let metadata = std::fs::metadata(&active)?;
+        let size = metadata.len();
+        let rotated = self.current_path.as_deref() != Some(active.as_path())
+            || size < self.offset
+            || self.inode_changed(&metadata);
+
+        if rotated {
+            log::info!("Log rotation detected, reopening {:?}", active);
+            self.current_path = Some(active.clone());
+            self.offset = 0;
+            #[cfg(unix)]
+            {
+                self.inode = Some(metadata.ino());
+            }
+        }
+
+        let mut file = File::open(&active)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = String::new();
+        let read = file.read_to_string(&mut buf)?;
+        self.offset += read as u64;
+
+        Ok(buf)
+    }
+
+    #[cfg(unix)]
+    fn inode_changed(&self, metadata: &std::fs::Metadata) -> bool {
+        self.inode.map(|i| i != metadata.ino()).unwrap_or(false)
+    }
+
+    #[cfg(not(unix))]
+    fn inode_changed(&self, _metadata: &std::fs::Metadata) -> bool {
+        false
+    }
+}
+
+/// Drive a `LogTailer` on its own interval for as long as the caller keeps
+/// polling, handing each non-empty chunk to `on_chunk`.
+pub async fn run_tail_loop<F>(mut tailer: LogTailer, mut on_chunk: F)
+where
+    F: FnMut(String) + Send,
+{
+    loop {
+        match tailer.poll() {
+            Ok(chunk) if !chunk.is_empty() => on_chunk(chunk),
+            Ok(_) => {}
+            Err(e) => log::warn!("Log tail poll failed: {}", e),
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
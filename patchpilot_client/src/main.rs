@@ -1,11 +1,16 @@
 mod action;
 mod command;
 mod device;
-mod remote_cmd;
+mod error_report;
+mod log_tail;
+mod pty_shell;
+mod relay_listener;
 mod self_update;
 mod patchpilot_updater;
+mod spool;
 mod system_info;
 mod service;
+mod watcher;
 
 use std::{fs, path::Path};
 use crate::service::init_logging;
@@ -147,6 +152,107 @@ WantedBy=multi-user.target
     Ok(())
 }
 
+#[cfg(target_os = "macos")]
+fn ensure_launchd_service() -> Result<(), Box<dyn std::error::Error>> {
+    let plist_path = "/Library/LaunchDaemons/com.patchpilot.client.plist";
+    let base_dir = get_base_dir();
+    let label = "com.patchpilot.client";
+
+    // Write out the LaunchDaemon plist if missing
+    if !Path::new(plist_path).exists() {
+        let plist_contents = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{base_dir}/patchpilot_client</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{base_dir}</string>
+    <key>KeepAlive</key>
+    <true/>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>StandardOutPath</key>
+    <string>{base_dir}/logs/patchpilot_current.log</string>
+    <key>StandardErrorPath</key>
+    <string>{base_dir}/logs/patchpilot_current.log</string>
+</dict>
+</plist>
+"#
+        );
+        fs::write(plist_path, plist_contents)?;
+    }
+
+    // Bootstrap (or re-bootstrap) the daemon into the system domain
+    let status = std::process::Command::new("launchctl")
+        .arg("bootstrap")
+        .arg("system")
+        .arg(plist_path)
+        .output();
+    if let Ok(out) = status {
+        if !out.status.success() {
+            // Already bootstrapped is the common "failure" case; just
+            // make sure it's enabled and kicked off.
+            let _ = std::process::Command::new("launchctl")
+                .arg("enable")
+                .arg(format!("system/{label}"))
+                .output();
+            let _ = std::process::Command::new("launchctl")
+                .arg("kickstart")
+                .arg("-k")
+                .arg(format!("system/{label}"))
+                .output();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn ensure_windows_service() -> Result<(), Box<dyn std::error::Error>> {
+    let service_name = "PatchPilotClient";
+    let base_dir = get_base_dir();
+    let exe_path = format!("{}\\patchpilot_client.exe", base_dir);
+
+    // Register the service if it isn't already known to the SCM
+    let query = std::process::Command::new("sc")
+        .arg("query")
+        .arg(service_name)
+        .output();
+    let needs_create = query.map(|out| !out.status.success()).unwrap_or(true);
+
+    if needs_create {
+        let _ = std::process::Command::new("sc")
+            .arg("create")
+            .arg(service_name)
+            .arg("binPath=").arg(format!("\"{}\"", exe_path))
+            .arg("start=").arg("auto")
+            .arg("DisplayName=").arg("PatchPilot Client")
+            .output();
+    }
+
+    // Auto-restart on crash, mirroring systemd's `Restart=always`
+    let _ = std::process::Command::new("sc")
+        .arg("failure")
+        .arg(service_name)
+        .arg("reset=").arg("86400")
+        .arg("actions=").arg("restart/5000/restart/5000/restart/5000")
+        .output();
+
+    // Start it now if it isn't already running
+    let _ = std::process::Command::new("sc")
+        .arg("start")
+        .arg(service_name)
+        .output();
+
+    Ok(())
+}
+
 fn log_initial_system_info() {
     use system_info::SystemInfo;
     let info = SystemInfo::gather_blocking();
@@ -174,6 +280,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(target_os = "linux")]
     ensure_systemd_service()?;
 
+    #[cfg(target_os = "macos")]
+    ensure_launchd_service()?;
+
+    #[cfg(windows)]
+    ensure_windows_service()?;
+
     log::info!("PatchPilot client starting…");
     log_initial_system_info();
 
@@ -0,0 +1,140 @@
+//! Interactive PTY-backed shell sessions, opened on demand by an operator
+//! and driven by `command::run_pty_session`, which posts output frames to
+//! and polls control frames from the server's `/shell/<session>/...` HTTP
+//! routes. The relay connection (`relay_listener.rs`) does not carry shell
+//! traffic. This is the "live shell" counterpart to the fire-and-forget
+//! jobs in `command.rs`.
+use anyhow::{Context, Result};
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// Control messages the dashboard can send into a live session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ShellControl {
+    #[serde(rename = "stdin")]
+    Stdin { session_id: String, data_base64: String },
+    #[serde(rename = "resize")]
+    Resize { session_id: String, rows: u16, cols: u16 },
+    #[serde(rename = "kill")]
+    Kill { session_id: String },
+}
+
+/// Frames the agent streams back to the dashboard.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ShellFrame {
+    #[serde(rename = "output")]
+    Output { session_id: String, data_base64: String },
+    #[serde(rename = "exit")]
+    Exit { session_id: String, code: i32 },
+}
+
+/// A single live interactive shell session.
+pub struct ShellSession {
+    pub id: String,
+    control_tx: Sender<ShellControl>,
+    frame_rx: Receiver<ShellFrame>,
+}
+
+impl ShellSession {
+    /// Allocate a PTY and spawn the shell attached to it. The returned
+    /// session owns a background thread pumping PTY output into `frame_rx`
+    /// and writing `control_tx` input/resize requests into the PTY.
+    pub fn spawn(id: String, shell: &str) -> Result<Self> {
+        let pty_system = native_pty_system();
+        let pair = pty_system
+            .openpty(PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("failed to allocate pty")?;
+
+        let cmd = CommandBuilder::new(shell);
+        let mut child = pair.slave.spawn_command(cmd).context("failed to spawn shell in pty")?;
+
+        let mut reader = pair.master.try_clone_reader().context("clone pty reader")?;
+        let mut writer = pair.master.take_writer().context("take pty writer")?;
+
+        let (control_tx, control_rx) = channel::<ShellControl>();
+        let (frame_tx, frame_rx) = channel::<ShellFrame>();
+
+        let session_id = id.clone();
+        let frame_tx_out = frame_tx.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        let data_base64 = base64::engine::general_purpose::STANDARD
+                            .encode(&buf[..n]);
+                        let _ = frame_tx_out.send(ShellFrame::Output {
+                            session_id: session_id.clone(),
+                            data_base64,
+                        });
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let session_id = id.clone();
+        thread::spawn(move || {
+            let exit_code = match child.wait() {
+                Ok(status) => status.exit_code() as i32,
+                Err(_) => -1,
+            };
+            let _ = frame_tx.send(ShellFrame::Exit {
+                session_id,
+                code: exit_code,
+            });
+        });
+
+        let session_id = id.clone();
+        thread::spawn(move || {
+            while let Ok(ctrl) = control_rx.recv() {
+                match ctrl {
+                    ShellControl::Stdin { data_base64, .. } => {
+                        if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(data_base64) {
+                            let _ = writer.write_all(&bytes);
+                        }
+                    }
+                    ShellControl::Resize { rows, cols, .. } => {
+                        let _ = pair.master.resize(PtySize {
+                            rows,
+                            cols,
+                            pixel_width: 0,
+                            pixel_height: 0,
+                        });
+                    }
+                    ShellControl::Kill { .. } => break,
+                }
+            }
+            log::info!("Shell session {} control loop ended", session_id);
+        });
+
+        Ok(Self {
+            id,
+            control_tx,
+            frame_rx,
+        })
+    }
+
+    pub fn send_control(&self, ctrl: ShellControl) -> Result<()> {
+        self.control_tx.send(ctrl).context("shell session control channel closed")
+    }
+
+    /// Drain whatever output frames are currently buffered; polled by
+    /// `command::run_pty_session`, which POSTs each one onward to the server.
+    pub fn try_recv_frames(&self) -> Vec<ShellFrame> {
+        self.frame_rx.try_iter().collect()
+    }
+}
+
+use base64::Engine as _;
@@ -0,0 +1,136 @@
+//! Client side of the reverse relay (see server's `relay.rs`): keeps a
+//! long-lived SSE connection open to `/devices/{id}/relay` so a freshly
+//! submitted action reaches us the moment it's created instead of waiting
+//! up to `action::COMMAND_POLL_INTERVAL_SECS` for the next poll. The push
+//! itself only carries enough to know "something changed" — on receiving
+//! one we immediately run the same poll-and-dispatch `action.rs` already
+//! does on its interval, so the two paths share one source of truth for
+//! what an action actually looks like.
+//!
+//! The connection is best-effort: if it drops or never connects (proxy
+//! stripping long-lived responses, a restart on the server side, etc.) we
+//! reconnect with exponential backoff, and `action_loop`'s own polling
+//! interval keeps actions flowing in the meantime either way.
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use reqwest::Client;
+
+use crate::action::{execute_action, poll_for_commands_once};
+use crate::error_report::ErrorReportSender;
+
+/// Initial reconnect delay; doubles on each consecutive failure up to
+/// `MAX_RECONNECT_BACKOFF_SECS`.
+const INITIAL_RECONNECT_BACKOFF_SECS: u64 = 1;
+const MAX_RECONNECT_BACKOFF_SECS: u64 = 60;
+
+/// Open the relay connection and process pushed events until
+/// `running_flag` is cleared, reconnecting with backoff on every drop.
+/// Runs forever (or until shutdown) — spawn it as a background task
+/// alongside `action::action_loop`.
+pub async fn run_relay_listener(
+    client: Client,
+    server_url: String,
+    device_id: String,
+    running_flag: Option<Arc<AtomicBool>>,
+    report_tx: ErrorReportSender,
+) {
+    let mut backoff_secs = INITIAL_RECONNECT_BACKOFF_SECS;
+
+    loop {
+        if let Some(flag) = &running_flag {
+            if !flag.load(Ordering::SeqCst) {
+                log::info!("Relay listener stopping due to shutdown flag");
+                return;
+            }
+        }
+
+        match listen_once(&client, &server_url, &device_id, &running_flag, &report_tx).await {
+            Ok(()) => {
+                // Clean end of stream (server closed it deliberately) —
+                // reconnect promptly rather than backing off.
+                backoff_secs = INITIAL_RECONNECT_BACKOFF_SECS;
+            }
+            Err(e) => {
+                log::warn!("Relay connection lost, reconnecting in {}s: {}", backoff_secs, e);
+                tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+                backoff_secs = (backoff_secs * 2).min(MAX_RECONNECT_BACKOFF_SECS);
+            }
+        }
+    }
+}
+
+/// Hold one relay connection open and dispatch on every event it sends,
+/// returning once the stream ends (cleanly or otherwise).
+async fn listen_once(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    running_flag: &Option<Arc<AtomicBool>>,
+    report_tx: &ErrorReportSender,
+) -> anyhow::Result<()> {
+    let resp = client
+        .get(format!("{}/api/v1/devices/{}/relay", server_url, device_id))
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Relay connection rejected: {}", resp.status());
+    }
+
+    let mut stream = resp.bytes_stream();
+    let mut buf = String::new();
+
+    while let Some(chunk) = stream.next().await {
+        if let Some(flag) = running_flag {
+            if !flag.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+        }
+
+        buf.push_str(&String::from_utf8_lossy(&chunk?));
+
+        // SSE frames are separated by a blank line; a `data:` line inside
+        // one carries the JSON payload (see `rocket::response::stream::Event::json`).
+        while let Some(idx) = buf.find("\n\n") {
+            let frame = buf[..idx].to_string();
+            buf.drain(..idx + 2);
+
+            for line in frame.lines() {
+                if let Some(data) = line.strip_prefix("data:") {
+                    on_action_pushed(client, server_url, device_id, data.trim(), report_tx).await;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A push notification arrived — we don't trust its payload directly (it's
+/// just a signal), so immediately poll for whatever's actually pending and
+/// dispatch it the same way `action::action_loop` would on its own
+/// interval.
+async fn on_action_pushed(
+    client: &Client,
+    server_url: &str,
+    device_id: &str,
+    _raw_event: &str,
+    report_tx: &ErrorReportSender,
+) {
+    match poll_for_commands_once(client, server_url, device_id).await {
+        Ok(commands) => {
+            for cmd in commands {
+                let c = client.clone();
+                let s = server_url.to_string();
+                let d = device_id.to_string();
+                let rt = report_tx.clone();
+                tokio::spawn(async move {
+                    execute_action(c, s, d, cmd, rt).await;
+                });
+            }
+        }
+        Err(e) => log::warn!("Push-triggered command poll failed: {}", e),
+    }
+}
@@ -1,9 +1,12 @@
 use anyhow::{bail, Result};
+use ed25519_dalek::{Signature, VerifyingKey, Verifier};
 use reqwest::blocking::Client;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::{
     env,
     fs,
+    io::Read,
     path::PathBuf,
     process::{Command, exit},
     time::Duration,
@@ -51,6 +54,14 @@ const RUNTIME_DIR: &str = "/opt/patchpilot_client";
 #[cfg(target_os = "macos")]
 const RUNTIME_DIR: &str = "/Library/Application Support/PatchPilot";
 
+/// Ed25519 public key used to verify a release's detached signature, when
+/// one is published (see `verify_release_signature`). Replace with the
+/// real release-signing key before cutting a signed release; left
+/// all-zero here means signature verification is effectively opt-in per
+/// release — a release without a `.sig` asset is simply skipped rather
+/// than rejected, so this placeholder doesn't brick updates.
+const RELEASE_SIGNING_PUBKEY: [u8; 32] = [0u8; 32];
+
 
 /// Checks GitHub releases and updates the agent if needed
 pub fn check_and_update() -> Result<()> {
@@ -102,6 +113,21 @@ pub fn check_and_update() -> Result<()> {
         new_exe_path.display()
     );
 
+    // Verify the download against the release's published checksum before
+    // ever handing it to the updater — a compromised mirror or MITM
+    // shouldn't be able to get an arbitrary binary executed.
+    if let Err(e) = verify_checksum(&client, &resp.assets, &new_exe_path) {
+        let _ = fs::remove_file(&new_exe_path);
+        return Err(e);
+    }
+
+    // Signature verification is best-effort: only enforced when the
+    // release actually published a detached signature.
+    if let Err(e) = verify_release_signature(&client, &resp.assets, &new_exe_path) {
+        let _ = fs::remove_file(&new_exe_path);
+        return Err(e);
+    }
+
     // Determine updater path
     let updater_path = PathBuf::from(RUNTIME_DIR).join(UPDATER_NAME);
 
@@ -149,3 +175,92 @@ fn download_file(client: &Client, url: &str, dest: &PathBuf) -> Result<()> {
     log::info!("Download complete.");
     Ok(())
 }
+
+/// Download the release's companion `<EXE_NAME>.sha256` asset and compare
+/// its published digest against what we actually downloaded, so a
+/// compromised mirror or MITM'd download gets caught before the updater
+/// ever runs it.
+fn verify_checksum(client: &Client, assets: &[ReleaseAsset], exe_path: &PathBuf) -> Result<()> {
+    let checksum_name = format!("{}.sha256", EXE_NAME);
+    let asset = assets
+        .iter()
+        .find(|a| a.name == checksum_name)
+        .ok_or_else(|| anyhow::anyhow!("Checksum asset '{}' not found in release assets", checksum_name))?;
+
+    let body = client
+        .get(&asset.browser_download_url)
+        .header("User-Agent", "PatchPilotUpdater")
+        .send()?
+        .error_for_status()?
+        .text()?;
+
+    // Accept either a bare hex digest or the standard `sha256sum` output
+    // format ("<digest>  <filename>").
+    let expected = body
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Checksum asset '{}' was empty", checksum_name))?
+        .to_lowercase();
+
+    let mut file = fs::File::open(exe_path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let actual = format!("{:x}", hasher.finalize());
+
+    if actual != expected {
+        bail!(
+            "Checksum mismatch for {}: expected {}, got {} — refusing to launch updater",
+            exe_path.display(),
+            expected,
+            actual
+        );
+    }
+
+    log::info!("Checksum verified: {}", actual);
+    Ok(())
+}
+
+/// Verify a detached Ed25519 signature over the downloaded binary, if the
+/// release published one (`<EXE_NAME>.sig`, the raw 64-byte signature).
+/// Releases that don't carry one are let through unsigned — this is a
+/// defense-in-depth check on top of the checksum, not a hard requirement —
+/// but any signature that *is* published must verify against
+/// `RELEASE_SIGNING_PUBKEY`, not just be well-formed.
+fn verify_release_signature(client: &Client, assets: &[ReleaseAsset], exe_path: &PathBuf) -> Result<()> {
+    let sig_name = format!("{}.sig", EXE_NAME);
+    let Some(asset) = assets.iter().find(|a| a.name == sig_name) else {
+        log::debug!("No detached signature asset '{}' published for this release", sig_name);
+        return Ok(());
+    };
+
+    let sig_bytes = client
+        .get(&asset.browser_download_url)
+        .header("User-Agent", "PatchPilotUpdater")
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+
+    let sig_bytes: [u8; 64] = sig_bytes
+        .as_ref()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Signature asset '{}' is not a valid 64-byte Ed25519 signature", sig_name))?;
+    let signature = Signature::from_bytes(&sig_bytes);
+
+    let verifying_key = VerifyingKey::from_bytes(&RELEASE_SIGNING_PUBKEY)
+        .map_err(|e| anyhow::anyhow!("Invalid baked-in release signing key: {}", e))?;
+
+    let file_bytes = fs::read(exe_path)?;
+    verifying_key
+        .verify(&file_bytes, &signature)
+        .map_err(|_| anyhow::anyhow!("Signature verification failed for {} — refusing to launch updater", exe_path.display()))?;
+
+    log::info!("Release signature verified for {}", exe_path.display());
+    Ok(())
+}
@@ -8,6 +8,8 @@ use tokio::signal::ctrl_c;
 
 use crate::action::{self, action_loop};
 use crate::device::run_adoption_and_update_loop;
+use crate::error_report;
+use crate::relay_listener::run_relay_listener;
 use crate::system_info::{self, get_system_info_refresh_secs, read_server_url, SystemInfoService};
 use crate::command;
 
@@ -37,7 +39,11 @@ pub fn init_logging() -> anyhow::Result<flexi_logger::LoggerHandle> {
     Ok(handle)
 }
 
-/// Common shutdown signal setup
+/// Common shutdown signal setup. Listens for Ctrl-C (SIGINT) everywhere,
+/// plus SIGTERM on Unix — the signal `systemctl stop`/`docker stop`/process
+/// supervisors actually send, so the service gets the same chance to drain
+/// in-flight commands and flush the spool on a managed stop as it does on
+/// an interactive Ctrl-C.
 async fn setup_shutdown_signal(running_flag: Arc<AtomicBool>) {
     let flag = running_flag.clone();
     tokio::spawn(async move {
@@ -45,12 +51,27 @@ async fn setup_shutdown_signal(running_flag: Arc<AtomicBool>) {
         println!("CTRL-C received, shutting down…");
         flag.store(false, Ordering::SeqCst);
     });
+
+    #[cfg(unix)]
+    {
+        let flag = running_flag.clone();
+        tokio::spawn(async move {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut term) => {
+                    term.recv().await;
+                    println!("SIGTERM received, shutting down…");
+                    flag.store(false, Ordering::SeqCst);
+                }
+                Err(e) => eprintln!("Failed to install SIGTERM handler: {:?}", e),
+            }
+        });
+    }
 }
 
 /// Unix service entrypoint
 #[cfg(any(unix, target_os = "macos"))]
 pub async fn run_unix_service() -> Result<()> {
-    let client = Client::new();
+    let client = crate::device::build_client()?;
     let server_url = read_server_url().await?;
     let running_flag = Arc::new(AtomicBool::new(true));
 
@@ -71,8 +92,24 @@ pub async fn run_unix_service() -> Result<()> {
         crate::service::system_info_loop(svc_clone, rf_clone, client_clone, srv_clone, dev_clone).await;
     });
 
+    // Central channel for command execution/post failures that would
+    // otherwise be dropped after a single log line (see `error_report.rs`).
+    let (report_tx, report_rx) = error_report::channel();
+    tokio::spawn(error_report::run_report_task(client.clone(), server_url.clone(), report_rx));
+
+    // Listen for pushed actions over the relay connection; action_loop's
+    // own polling interval below is the fallback if the relay is down.
+    let relay_client = client.clone();
+    let relay_server_url = server_url.clone();
+    let relay_device_id = device_id.clone();
+    let relay_running_flag = running_flag.clone();
+    let relay_report_tx = report_tx.clone();
+    tokio::spawn(async move {
+        run_relay_listener(relay_client, relay_server_url, relay_device_id, Some(relay_running_flag), relay_report_tx).await;
+    });
+
     // Start action loop
-    action_loop(client.clone(), server_url.clone(), device_id.clone(), Some(running_flag.clone())).await?;
+    action_loop(client.clone(), server_url.clone(), device_id.clone(), Some(running_flag.clone()), report_tx).await?;
 
     Ok(())
 }
@@ -80,7 +117,7 @@ pub async fn run_unix_service() -> Result<()> {
 /// Windows service entrypoint
 #[cfg(windows)]
 pub async fn run_service(running_flag: Arc<AtomicBool>) -> Result<()> {
-    let client = Client::new();
+    let client = crate::device::build_client()?;
     let server_url = system_info::read_server_url().await?;
 
     // Device registration and adoption
@@ -97,8 +134,24 @@ pub async fn run_service(running_flag: Arc<AtomicBool>) -> Result<()> {
         crate::service::system_info_loop(svc_clone, rf_clone, client_clone, srv_clone, dev_clone).await;
     });
 
+    // Central channel for command execution/post failures that would
+    // otherwise be dropped after a single log line (see `error_report.rs`).
+    let (report_tx, report_rx) = error_report::channel();
+    tokio::spawn(error_report::run_report_task(client.clone(), server_url.clone(), report_rx));
+
+    // Listen for pushed actions over the relay connection; action_loop's
+    // own polling interval below is the fallback if the relay is down.
+    let relay_client = client.clone();
+    let relay_server_url = server_url.clone();
+    let relay_device_id = device_id.clone();
+    let relay_running_flag = running_flag.clone();
+    let relay_report_tx = report_tx.clone();
+    tokio::spawn(async move {
+        run_relay_listener(relay_client, relay_server_url, relay_device_id, Some(relay_running_flag), relay_report_tx).await;
+    });
+
     // Start action loop
-    action_loop(client.clone(), server_url.clone(), device_id.clone(), Some(running_flag.clone())).await?;
+    action_loop(client.clone(), server_url.clone(), device_id.clone(), Some(running_flag.clone()), report_tx).await?;
 
     Ok(())
 }
@@ -117,18 +170,28 @@ pub async fn system_info_loop(
             Ok(info) => {
                 println!("Collected system info: {:?}", info);
                 let url = format!("{}/api/devices/{}/system_info", server_url, device_id);
-                let client_clone = client.clone();
-                let info_clone = info.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = client_clone.post(&url).json(&info_clone).send().await {
-                        eprintln!("Failed to send system info: {:?}", e);
-                    }
-                });
+                // Awaited in-loop (not a detached `tokio::spawn`) so a slow
+                // or hung POST is bounded by this timeout instead of
+                // leaking an unsupervised task past a shutdown request.
+                match tokio::time::timeout(
+                    Duration::from_secs(30),
+                    client.post(&url).json(&info).send(),
+                )
+                .await
+                {
+                    Ok(Err(e)) => eprintln!("Failed to send system info: {:?}", e),
+                    Err(_) => eprintln!("Timed out sending system info"),
+                    Ok(Ok(_)) => {}
+                }
             }
             Err(e) => {
                 eprintln!("Failed to collect system info: {:?}", e);
             }
         }
+
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
         sleep(interval).await;
     }
 }
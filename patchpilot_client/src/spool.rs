@@ -0,0 +1,242 @@
+//! Durable on-disk queue for the async command channel (`action.rs` /
+//! `command.rs`). Two problems it fixes:
+//!
+//! - A `ServerCommand` picked up from `poll_for_commands_once` that's still
+//!   running when the process is killed (crash, reboot, `systemctl
+//!   restart`) used to just vanish — nothing re-ran it and the server never
+//!   heard back. `enqueue_inbound`/`dequeue_inbound`/`replay_inbound` make
+//!   that durable: a command is written to disk before it starts executing
+//!   and only removed once it reaches a terminal state (its result queued
+//!   for posting, or execution failed outright).
+//! - A `CommandResult`/`OutputFrame` post that failed (server down, network
+//!   blip) used to just be logged and dropped. `queue_and_send_result` /
+//!   `queue_and_send_output` always write the entry to disk *before*
+//!   attempting the send, and only remove it on a 2xx — `flush_outbound`
+//!   (driven by `run_flush_task`) retries whatever's left with backoff.
+//!
+//! Each entry is a plain JSON file, written with async `tokio::fs` under
+//! its own `command_spool` subdirectory of the client's base directory.
+use crate::action::{CommandResult, OutputFrame, ServerCommand};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+/// How often `run_flush_task` retries a non-empty outbound queue while the
+/// server keeps rejecting it; doubles on every failed pass up to this cap.
+const MAX_FLUSH_BACKOFF_SECS: u64 = 60;
+const INITIAL_FLUSH_BACKOFF_SECS: u64 = 2;
+/// How long to sleep between flush passes once the queue is empty.
+const FLUSH_IDLE_INTERVAL_SECS: u64 = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpoolError {
+    #[error("failed to create spool directory {0:?}: {1}")]
+    CreateDir(PathBuf, std::io::Error),
+    #[error("failed to write spool entry {0:?}: {1}")]
+    Write(PathBuf, std::io::Error),
+    #[error("failed to read spool directory {0:?}: {1}")]
+    ReadDir(PathBuf, std::io::Error),
+    #[error("failed to encode spool entry: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+fn spool_root() -> PathBuf {
+    Path::new(&crate::get_base_dir()).join("command_spool")
+}
+
+fn inbound_dir() -> PathBuf {
+    spool_root().join("inbound")
+}
+
+fn outbound_dir() -> PathBuf {
+    spool_root().join("outbound")
+}
+
+/// One pending outbound POST: either the terminal result or one streamed
+/// output frame for a still-running command.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind")]
+enum PendingPost {
+    Result { device_id: String, result: CommandResult },
+    Output { device_id: String, cmd_id: String, frame: OutputFrame },
+}
+
+/// Write `entry` to its own file under `dir`, named by the current time so
+/// a directory listing is already oldest-first. Returns the path written.
+async fn write_entry<T: Serialize>(dir: &Path, entry: &T) -> Result<PathBuf, SpoolError> {
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| SpoolError::CreateDir(dir.to_path_buf(), e))?;
+
+    let queued_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let path = dir.join(format!("{queued_at}.json"));
+    let body = serde_json::to_vec(entry)?;
+    fs::write(&path, body)
+        .await
+        .map_err(|e| SpoolError::Write(path.clone(), e))?;
+    Ok(path)
+}
+
+/// List every entry under `dir`, oldest first, dropping (and deleting) any
+/// that fail to decode as `T`.
+async fn read_entries<T: for<'de> Deserialize<'de>>(dir: &Path) -> Vec<(PathBuf, T)> {
+    let mut reader = match fs::read_dir(dir).await {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            log::warn!("Failed to read spool directory {:?}: {}", dir, e);
+            return Vec::new();
+        }
+    };
+
+    let mut paths = Vec::new();
+    while let Ok(Some(entry)) = reader.next_entry().await {
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    let mut out = Vec::with_capacity(paths.len());
+    for path in paths {
+        match fs::read(&path).await {
+            Ok(bytes) => match serde_json::from_slice::<T>(&bytes) {
+                Ok(value) => out.push((path, value)),
+                Err(_) => {
+                    log::warn!("Dropping unreadable spool entry {:?}", path);
+                    let _ = fs::remove_file(&path).await;
+                }
+            },
+            Err(_) => continue,
+        }
+    }
+    out
+}
+
+/// Persist a `ServerCommand` before dispatching it for execution, so a
+/// crash/reboot mid-run can still pick it back up (see `replay_inbound`).
+pub async fn enqueue_inbound(cmd: &ServerCommand) {
+    if let Err(e) = write_entry(&inbound_dir(), cmd).await {
+        log::warn!("Failed to spool inbound command {}: {}", cmd.id, e);
+    }
+}
+
+/// Remove `cmd_id` from the inbound queue once it's reached a terminal
+/// state, whether its result was handed to `queue_and_send_result` or
+/// execution failed outright.
+pub async fn dequeue_inbound(cmd_id: &str) {
+    for (path, cmd) in read_entries::<ServerCommand>(&inbound_dir()).await {
+        if cmd.id == cmd_id {
+            let _ = fs::remove_file(&path).await;
+        }
+    }
+}
+
+/// Any `ServerCommand`s left in the inbound queue from a previous run that
+/// never reached a terminal state — e.g. the process was killed mid-run.
+/// Called once at `action_loop` startup so they still execute and report
+/// instead of silently vanishing.
+pub async fn replay_inbound() -> Vec<ServerCommand> {
+    read_entries::<ServerCommand>(&inbound_dir())
+        .await
+        .into_iter()
+        .map(|(_, cmd)| cmd)
+        .collect()
+}
+
+/// Queue a terminal `CommandResult` and attempt to send it right away;
+/// leaves it queued for `flush_outbound` on anything short of a 2xx.
+pub async fn queue_and_send_result(client: &Client, server_url: &str, device_id: &str, result: CommandResult) {
+    let cmd_id = result.id.clone();
+    let post = PendingPost::Result { device_id: device_id.to_string(), result };
+    send_or_queue(client, server_url, post, &cmd_id).await;
+}
+
+/// Queue a single streamed `OutputFrame` and attempt to send it right
+/// away; same durability guarantee as `queue_and_send_result`.
+pub async fn queue_and_send_output(client: &Client, server_url: &str, device_id: &str, cmd_id: String, frame: OutputFrame) {
+    let post = PendingPost::Output { device_id: device_id.to_string(), cmd_id: cmd_id.clone(), frame };
+    send_or_queue(client, server_url, post, &cmd_id).await;
+}
+
+async fn send_or_queue(client: &Client, server_url: &str, post: PendingPost, cmd_id: &str) {
+    let path = match write_entry(&outbound_dir(), &post).await {
+        Ok(p) => p,
+        Err(e) => {
+            // Couldn't even get it on disk — still worth a best-effort
+            // direct send rather than dropping it outright.
+            log::error!("Failed to spool outbound post for {}: {}", cmd_id, e);
+            let _ = send_post(client, server_url, &post).await;
+            return;
+        }
+    };
+
+    match send_post(client, server_url, &post).await {
+        Ok(true) => {
+            let _ = fs::remove_file(&path).await;
+        }
+        Ok(false) => {
+            log::warn!("Server rejected queued post for {}, will retry", cmd_id);
+        }
+        Err(e) => {
+            log::warn!("Failed to send queued post for {}, will retry: {}", cmd_id, e);
+        }
+    }
+}
+
+async fn send_post(client: &Client, server_url: &str, post: &PendingPost) -> anyhow::Result<bool> {
+    match post {
+        PendingPost::Result { device_id, result } => {
+            crate::command::post_command_result(client, server_url, device_id, &result.id, result).await
+        }
+        PendingPost::Output { device_id, cmd_id, frame } => {
+            crate::command::post_output_frame(client, server_url, device_id, cmd_id, frame).await
+        }
+    }
+}
+
+/// Drain every queued outbound post, oldest first, stopping at the first
+/// failure so ordering is preserved for the next flush attempt.
+pub async fn flush_outbound(client: &Client, server_url: &str) -> bool {
+    let entries = read_entries::<PendingPost>(&outbound_dir()).await;
+    if entries.is_empty() {
+        return true;
+    }
+
+    log::info!("Flushing {} queued command post(s) to the server", entries.len());
+    for (path, post) in entries {
+        match send_post(client, server_url, &post).await {
+            Ok(true) => {
+                let _ = fs::remove_file(&path).await;
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Background task: periodically drains the outbound queue, backing off
+/// while the server keeps rejecting it and resetting to a quick idle poll
+/// once the queue drains clean. Spawn alongside `action_loop`.
+pub async fn run_flush_task(client: Client, server_url: String, running_flag: Option<Arc<AtomicBool>>) {
+    let mut backoff_secs = INITIAL_FLUSH_BACKOFF_SECS;
+    loop {
+        if let Some(flag) = &running_flag {
+            if !flag.load(Ordering::SeqCst) {
+                return;
+            }
+        }
+
+        if flush_outbound(&client, &server_url).await {
+            backoff_secs = INITIAL_FLUSH_BACKOFF_SECS;
+            tokio::time::sleep(Duration::from_secs(FLUSH_IDLE_INTERVAL_SECS)).await;
+        } else {
+            tokio::time::sleep(Duration::from_secs(backoff_secs)).await;
+            backoff_secs = (backoff_secs * 2).min(MAX_FLUSH_BACKOFF_SECS);
+        }
+    }
+}
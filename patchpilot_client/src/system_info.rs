@@ -11,7 +11,7 @@ use std::{
 };
 
 use local_ip_address::local_ip;
-use sysinfo::{System, Disks, Networks};
+use sysinfo::{Components, System, Disks, Networks};
 
 /// Default refresh interval (seconds)
 static SYSTEM_INFO_REFRESH_SECS: AtomicU64 = AtomicU64::new(10);
@@ -34,6 +34,33 @@ const SERVER_URL_FILE: &str = "/opt/patchpilot_client/server_url.txt";
 #[cfg(windows)]
 const SERVER_URL_FILE: &str = "C:\\ProgramData\\PatchPilot\\server_url.txt";
 
+#[cfg(any(unix, target_os = "macos"))]
+const SIGNING_KEY_FILE: &str = "/opt/patchpilot_client/signing_key.txt";
+#[cfg(windows)]
+const SIGNING_KEY_FILE: &str = "C:\\ProgramData\\PatchPilot\\signing_key.txt";
+
+#[cfg(any(unix, target_os = "macos"))]
+const REFRESH_TOKEN_FILE: &str = "/opt/patchpilot_client/refresh_token.txt";
+#[cfg(windows)]
+const REFRESH_TOKEN_FILE: &str = "C:\\ProgramData\\PatchPilot\\refresh_token.txt";
+
+/// Optional operator-supplied list of `host:port` reachability probes, one
+/// per line. Missing or empty means only the server's own round-trip
+/// latency is measured each heartbeat.
+#[cfg(any(unix, target_os = "macos"))]
+const PROBE_TARGETS_FILE: &str = "/opt/patchpilot_client/probe_targets.txt";
+#[cfg(windows)]
+const PROBE_TARGETS_FILE: &str = "C:\\ProgramData\\PatchPilot\\probe_targets.txt";
+
+/// One configured probe target's latest measurement (see
+/// `device::measure_probe_targets`).
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct ProbeResult {
+    pub target: String,
+    pub ping_ms: Option<f32>,
+    pub up: bool,
+}
+
 /// Matches server-side expectations (extra fields are ignored server-side)
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct SystemInfo {
@@ -56,6 +83,18 @@ pub struct SystemInfo {
     pub network_interfaces: Option<Vec<String>>,
     pub ip_address: Option<String>,
 
+    /// Seconds since this host booted, straight from the OS (`sysinfo`'s
+    /// `/proc/uptime`-equivalent on every supported platform) — not to be
+    /// confused with the dashboard's own "time since last check-in" figure.
+    pub uptime_secs: i64,
+
+    /// Round-trip TCP latency to the server itself, measured fresh on each
+    /// heartbeat (see `device::send_heartbeat`).
+    pub server_latency_ms: Option<f32>,
+    /// Latest reachability reading for each configured probe target (see
+    /// `read_probe_targets`).
+    pub probe_results: Vec<ProbeResult>,
+
     pub device_type: String,
     pub device_model: String,
 }
@@ -101,6 +140,8 @@ impl SystemInfo {
             disk_free += disk.available_space() as i64;
         }
 
+        let disk_health = summarize_disk_health(&Components::new_with_refreshed_list());
+
         // ---- Network ----
         let networks = Networks::new_with_refreshed_list();
 
@@ -117,6 +158,8 @@ impl SystemInfo {
 
         let ip_address = local_ip().ok().map(|ip| ip.to_string());
 
+        let uptime_secs = System::uptime() as i64;
+
         SystemInfo {
             hostname,
             os_name,
@@ -131,12 +174,20 @@ impl SystemInfo {
 
             disk_total,
             disk_free,
-            disk_health: "unknown".to_string(),
+            disk_health,
 
             network_throughput,
             network_interfaces,
             ip_address,
 
+            uptime_secs,
+
+            // Populated separately, just before a heartbeat is sent (see
+            // `device::send_heartbeat`) — a plain system-info gather has no
+            // server to measure against yet.
+            server_latency_ms: None,
+            probe_results: Vec::new(),
+
             device_type: String::new(),
             device_model: String::new(),
         }
@@ -184,6 +235,36 @@ impl SystemInfoService {
     }
 }
 
+/// Temperature past which a disk/NVMe component is flagged instead of just
+/// reported — chosen well under the ~60-70C throttling point most consumer
+/// drives specify, so the server sees a warning before anything actually
+/// throttles.
+const DISK_WARN_TEMP_C: f32 = 55.0;
+
+/// Fold the disk/NVMe-labeled entries of `components` into a single
+/// human-readable health string: `"unknown"` if no such sensor exists on
+/// this machine (sysinfo has no generic cross-platform SMART API to fall
+/// back to), `"ok (NN.NC)"` for the hottest one otherwise, or
+/// `"warning: <label> at NN.NC"` if it's past `DISK_WARN_TEMP_C`.
+fn summarize_disk_health(components: &Components) -> String {
+    let hottest = components
+        .iter()
+        .filter(|c| {
+            let label = c.label().to_ascii_lowercase();
+            label.contains("disk") || label.contains("nvme") || label.contains("ssd") || label.contains("hdd")
+        })
+        .map(|c| (c.label(), c.temperature()))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    match hottest {
+        Some((label, temp)) if temp >= DISK_WARN_TEMP_C => {
+            format!("warning: {} at {:.1}C", label, temp)
+        }
+        Some((_, temp)) => format!("ok ({:.1}C)", temp),
+        None => "unknown".to_string(),
+    }
+}
+
 // ---- Helpers ----
 
 pub fn get_system_info() -> SystemInfo {
@@ -209,7 +290,62 @@ pub fn write_local_device_id(device_id: &str) -> Result<()> {
         .context("Failed to write local device_id")
 }
 
+/// The refresh token the server handed out once this device was approved
+/// (see `device::send_heartbeat`), if any has been persisted yet.
+pub fn get_local_refresh_token() -> Option<String> {
+    fs::read_to_string(REFRESH_TOKEN_FILE)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+pub fn write_local_refresh_token(refresh_token: &str) -> Result<()> {
+    fs::write(REFRESH_TOKEN_FILE, refresh_token)
+        .context("Failed to write local refresh_token")
+}
+
+/// Read the operator-configured `host:port` probe targets, one per line.
+/// Returns an empty list (not an error) if the file is missing, so probing
+/// stays opt-in.
+pub fn read_probe_targets() -> Vec<String> {
+    fs::read_to_string(PROBE_TARGETS_FILE)
+        .ok()
+        .map(|contents| {
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub fn get_device_info_basic() -> (String, String) {
     let si = get_system_info();
     (si.device_type, si.device_model)
 }
+
+/// Load this device's persisted Ed25519 identity key, generating and
+/// persisting a new one on first run. Signs every heartbeat going forward
+/// (see `device::send_heartbeat`) so a bare `device_id` is no longer enough
+/// to impersonate the device to the server.
+pub fn get_or_create_signing_key() -> Result<ed25519_dalek::SigningKey> {
+    use base64::Engine;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    if let Ok(existing) = fs::read_to_string(SIGNING_KEY_FILE) {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(existing.trim())
+            .context("Stored signing key is not valid base64")?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Stored signing key is the wrong length"))?;
+        return Ok(SigningKey::from_bytes(&bytes));
+    }
+
+    let signing_key = SigningKey::generate(&mut OsRng);
+    let encoded = base64::engine::general_purpose::STANDARD.encode(signing_key.to_bytes());
+    fs::write(SIGNING_KEY_FILE, encoded).context("Failed to persist signing key")?;
+    Ok(signing_key)
+}
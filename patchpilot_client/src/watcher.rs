@@ -0,0 +1,180 @@
+//! Filesystem watch sessions, opened on demand by a `CommandSpec::Watch`
+//! action (see `command::run_watch_session`) and torn down once the action
+//! expires or is canceled server-side. Mirrors the thread + mpsc
+//! shape of `pty_shell.rs`: a background thread owns the OS-level watcher
+//! and debouncing, the async side just drains whatever's ready.
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the agent was asked to watch, built from a `CommandSpec::Watch`.
+#[derive(Clone, Debug)]
+pub struct WatchSpec {
+    pub paths: Vec<String>,
+    pub recursive: bool,
+    pub debounce_ms: u64,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for WatchSpec {
+    fn default() -> Self {
+        WatchSpec {
+            paths: Vec::new(),
+            recursive: true,
+            debounce_ms: 500,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+/// A single filesystem change, batched up and POSTed to the server.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileChangeEvent {
+    pub path: String,
+    pub kind: String, // "created" | "modified" | "removed"
+    pub timestamp: String,
+}
+
+/// A crude include/exclude filter: a pattern matches if it's a literal
+/// substring of the path, or ends in `*` and matches as a prefix. Good
+/// enough for "watch /etc but skip /etc/cache/*" without pulling in a
+/// globbing crate for one feature.
+fn matches_pattern(path: &str, pattern: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix('*') {
+        path.starts_with(prefix)
+    } else {
+        path.contains(pattern)
+    }
+}
+
+fn passes_filters(path: &str, spec: &WatchSpec) -> bool {
+    if !spec.include.is_empty() && !spec.include.iter().any(|p| matches_pattern(path, p)) {
+        return false;
+    }
+    if spec.exclude.iter().any(|p| matches_pattern(path, p)) {
+        return false;
+    }
+    true
+}
+
+fn event_kind_name(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Create(_) => "created",
+        EventKind::Remove(_) => "removed",
+        _ => "modified",
+    }
+}
+
+/// A live watch session: owns the OS watcher and a debouncing thread that
+/// coalesces raw filesystem events into batches.
+pub struct WatchSession {
+    pub action_id: String,
+    _watcher: RecommendedWatcher,
+    batch_rx: Receiver<Vec<FileChangeEvent>>,
+    stop_tx: Sender<()>,
+}
+
+impl WatchSession {
+    pub fn spawn(action_id: String, spec: WatchSpec) -> Result<Self> {
+        let (raw_tx, raw_rx) = channel::<notify::Result<Event>>();
+        let mut watcher =
+            notify::recommended_watcher(raw_tx).context("failed to create filesystem watcher")?;
+
+        let mode = if spec.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        for path in &spec.paths {
+            watcher
+                .watch(std::path::Path::new(path), mode)
+                .with_context(|| format!("failed to watch {}", path))?;
+        }
+
+        let (batch_tx, batch_rx) = channel::<Vec<FileChangeEvent>>();
+        let (stop_tx, stop_rx) = channel::<()>();
+
+        let debounce = Duration::from_millis(spec.debounce_ms.max(50));
+        let spec_for_thread = spec.clone();
+        let action_id_for_thread = action_id.clone();
+
+        thread::spawn(move || {
+            let action_id = action_id_for_thread;
+            let mut pending: Vec<FileChangeEvent> = Vec::new();
+            let mut window_start: Option<Instant> = None;
+
+            loop {
+                if stop_rx.try_recv().is_ok() {
+                    break;
+                }
+
+                let wait = debounce
+                    .checked_sub(window_start.map(|s| s.elapsed()).unwrap_or(Duration::ZERO))
+                    .unwrap_or(Duration::ZERO);
+
+                match raw_rx.recv_timeout(wait.max(Duration::from_millis(10))) {
+                    Ok(Ok(event)) => {
+                        for p in flatten_paths(&event) {
+                            if !passes_filters(&p, &spec_for_thread) {
+                                continue;
+                            }
+                            if window_start.is_none() {
+                                window_start = Some(Instant::now());
+                            }
+                            pending.push(FileChangeEvent {
+                                path: p,
+                                kind: event_kind_name(&event.kind).to_string(),
+                                timestamp: chrono::Utc::now().to_rfc3339(),
+                            });
+                        }
+                    }
+                    Ok(Err(e)) => log::warn!("Watch session {} error: {:?}", action_id, e),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                if let Some(start) = window_start {
+                    if start.elapsed() >= debounce && !pending.is_empty() {
+                        let batch = std::mem::take(&mut pending);
+                        if batch_tx.send(batch).is_err() {
+                            break;
+                        }
+                        window_start = None;
+                    }
+                }
+            }
+            log::info!("Watch session {} stopped", action_id);
+        });
+
+        Ok(Self {
+            action_id,
+            _watcher: watcher,
+            batch_rx,
+            stop_tx,
+        })
+    }
+
+    /// Drain whatever debounced batches are ready to POST onward.
+    pub fn try_recv_batches(&self) -> Vec<Vec<FileChangeEvent>> {
+        self.batch_rx.try_iter().collect()
+    }
+
+    /// Tear down the OS watcher and stop the debounce thread.
+    pub fn stop(&self) {
+        let _ = self.stop_tx.send(());
+    }
+}
+
+fn flatten_paths(event: &Event) -> Vec<String> {
+    event
+        .paths
+        .iter()
+        .map(|p: &PathBuf| p.to_string_lossy().into_owned())
+        .collect()
+}
@@ -11,6 +11,16 @@ use std::time::Duration;
 use reqwest::blocking::Client;
 use serde_json::json;
 
+/// Protocol version this client speaks. Bump alongside any breaking change
+/// to the heartbeat/action payloads so the server can flag stale agents
+/// instead of silently mishandling them.
+const PROTOCOL_VERSION: i32 = 1;
+
+/// Capabilities this client actually implements: only the self-update
+/// check that `main` runs. The server only targets actions at
+/// capabilities a client has advertised.
+const CAPABILITIES: &[&str] = &["self_update"];
+
 #[cfg(not(windows))]
 fn run_linux_client_loop() -> Result<()> {
     info!("Linux Patch Client starting...");
@@ -29,13 +39,18 @@ fn run_linux_client_loop() -> Result<()> {
         let response = client.post(format!("{}/api/devices/heartbeat", server_url))
             .json(&json!( {
                 "client_id": "unique-client-id", // Use unique client ID here
-                "system_info": system_info // Add the actual system info
+                "system_info": system_info, // Add the actual system info
+                "protocol_version": PROTOCOL_VERSION,
+                "capabilities": CAPABILITIES,
             }))
             .send();
 
         match response {
             Ok(resp) if resp.status().is_success() => {
                 let status: serde_json::Value = resp.json()?;
+                if let Some(true) = status["protocol_outdated"].as_bool() {
+                    error!("Server reports this client's protocol version ({}) is outdated; some actions may not be dispatched.", PROTOCOL_VERSION);
+                }
                 if status["adopted"].as_bool() == Some(true) {
                     info!("Client approved. Starting system report loop...");
                     break; // Proceed to normal reporting after adoption
@@ -0,0 +1,82 @@
+//! Picks the active Diesel backend (sqlite / postgres / mysql) from Cargo
+//! features and exposes it to `src/` as the `db_backend` cfg, so exactly
+//! one of `diesel::sqlite::SqliteConnection`, `diesel::pg::PgConnection`, or
+//! `diesel::mysql::MysqlConnection` gets wired up in `db.rs`. Enforced here
+//! rather than with `#[cfg(all(feature = "sqlite", feature = "postgres"))]
+//! compile_error!` blocks sprinkled through the crate, since a build script
+//! can give one clear error instead of one per invalid combination.
+//!
+//! Also compiles every `schemas/*.capnp` file into the Rust bindings `rpc`
+//! consumes, so the wire format lives as source-controlled `.capnp` rather
+//! than generated code checked into the tree.
+use std::env;
+use std::path::Path;
+use walkdir::WalkDir;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(db_backend, values(\"sqlite\", \"postgres\", \"mysql\"))");
+
+    let sqlite = env::var("CARGO_FEATURE_SQLITE").is_ok();
+    let postgres = env::var("CARGO_FEATURE_POSTGRES").is_ok();
+    let mysql = env::var("CARGO_FEATURE_MYSQL").is_ok();
+
+    let enabled: Vec<&str> = [
+        (sqlite, "sqlite"),
+        (postgres, "postgres"),
+        (mysql, "mysql"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect();
+
+    match enabled.as_slice() {
+        [] => panic!(
+            "patchpilot_server: no database backend feature enabled. Enable exactly one of \
+             `sqlite`, `postgres`, or `mysql` (e.g. `cargo build --features postgres --no-default-features`)."
+        ),
+        [one] => println!("cargo:rustc-cfg=db_backend=\"{one}\""),
+        many => panic!(
+            "patchpilot_server: multiple database backend features enabled ({}). \
+             Exactly one of `sqlite`, `postgres`, or `mysql` may be active at a time.",
+            many.join(", ")
+        ),
+    }
+
+    compile_capnp_schemas();
+}
+
+/// Feed every non-hidden `*.capnp` file under `schemas/` to the `capnp`
+/// compiler. `walkdir` (rather than a flat `read_dir`) so schemas can be
+/// organized into subdirectories later without touching this function.
+fn compile_capnp_schemas() {
+    println!("cargo:rerun-if-changed=schemas");
+
+    let schema_dir = Path::new("schemas");
+    let mut command = capnpc::CompilerCommand::new();
+    command.src_prefix(schema_dir);
+
+    let mut found_any = false;
+    for entry in WalkDir::new(schema_dir).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+
+        let is_hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if is_hidden {
+            continue;
+        }
+
+        if path.extension().and_then(|ext| ext.to_str()) == Some("capnp") {
+            command.file(path);
+            found_any = true;
+        }
+    }
+
+    if found_any {
+        command
+            .run()
+            .expect("compiling schemas/*.capnp failed");
+    }
+}
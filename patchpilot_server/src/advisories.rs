@@ -0,0 +1,172 @@
+//! Security-advisory ingestion: polls a configurable list of vendor/distro
+//! RSS or Atom feeds, dedupes entries against the `advisories` table by
+//! GUID, and turns newly-seen advisories into proposed `NewAction`s
+//! targeting whichever devices' `os_name` the advisory mentions. Registered
+//! with the [`BackgroundRunner`](crate::background::BackgroundRunner) like
+//! any other maintenance worker.
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use feed_rs::parser;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::background::{BackgroundRunner, WorkerError};
+use crate::db::{log_audit, DbBackendConnection, DbPool};
+use crate::models::{Device, NewAction, NewAdvisory};
+use crate::schema::{action_targets, actions, advisories, devices};
+use crate::state::AppState;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(900);
+
+/// Feed URLs to poll, read from `ADVISORY_FEED_URLS` (comma-separated) so
+/// operators can point this at vendor/distro feeds without a rebuild.
+fn configured_feed_urls() -> Vec<String> {
+    std::env::var("ADVISORY_FEED_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+pub fn spawn_advisory_ingestion(runner: &BackgroundRunner, pool: DbPool, app_state: Arc<AppState>) {
+    runner.register("advisory_ingestion", POLL_INTERVAL, move || {
+        let pool = pool.clone();
+        let app_state = app_state.clone();
+        async move {
+            let feed_urls = configured_feed_urls();
+            if feed_urls.is_empty() {
+                return Ok(());
+            }
+
+            for feed_url in feed_urls {
+                if let Err(e) = ingest_feed(&pool, &app_state, &feed_url).await {
+                    tracing::warn!("Advisory feed {} failed: {}", feed_url, e);
+                }
+            }
+
+            Ok(())
+        }
+    });
+}
+
+async fn ingest_feed(pool: &DbPool, _app_state: &Arc<AppState>, feed_url: &str) -> Result<(), WorkerError> {
+    let body = reqwest::get(feed_url).await?.bytes().await?;
+    let feed = parser::parse(&body[..])?;
+
+    let feed_url = feed_url.to_string();
+    let conn = pool.get().await?;
+
+    conn.interact(move |conn| -> Result<(), WorkerError> {
+        for entry in feed.entries {
+            let guid = entry.id.clone();
+
+            let already_seen: bool = advisories::table
+                .filter(advisories::guid.eq(&guid))
+                .count()
+                .get_result::<i64>(conn)
+                .map(|n| n > 0)?;
+
+            if already_seen {
+                continue;
+            }
+
+            let title = entry
+                .title
+                .map(|t| t.content)
+                .unwrap_or_else(|| "(untitled advisory)".to_string());
+            let link = entry
+                .links
+                .first()
+                .map(|l| l.href.clone())
+                .unwrap_or_default();
+            let published_at = entry
+                .published
+                .or(entry.updated)
+                .map(|d| d.naive_utc())
+                .unwrap_or_else(|| Utc::now().naive_utc());
+
+            let matched_devices: Vec<Device> = devices::table
+                .load::<Device>(conn)?
+                .into_iter()
+                .filter(|d| title.to_lowercase().contains(&d.os_name.to_lowercase()))
+                .collect();
+
+            let os_match = matched_devices
+                .first()
+                .map(|d| d.os_name.clone());
+
+            diesel::insert_into(advisories::table)
+                .values(&NewAdvisory {
+                    guid: guid.clone(),
+                    source_url: feed_url.clone(),
+                    title: title.clone(),
+                    link: link.clone(),
+                    os_match,
+                    published_at,
+                    ingested_at: Utc::now().naive_utc(),
+                })
+                .execute(conn)?;
+
+            if matched_devices.is_empty() {
+                continue;
+            }
+
+            create_advisory_action(conn, &guid, &title, &link, &matched_devices)?;
+        }
+
+        Ok(())
+    })
+    .await
+    .unwrap_or_else(|e| Err(Box::new(e) as WorkerError))
+}
+
+/// Turn a newly-seen advisory into a proposed `NewAction`, targeting every
+/// device it matched, so it shows up on the existing actions page exactly
+/// like an operator-submitted action would.
+fn create_advisory_action(
+    conn: &mut DbBackendConnection,
+    guid: &str,
+    title: &str,
+    link: &str,
+    matched_devices: &[Device],
+) -> Result<(), WorkerError> {
+    let action_id = format!("advisory:{}", guid);
+    let now = Utc::now().naive_utc();
+    let expires_at: NaiveDateTime = now + chrono::Duration::seconds(86_400);
+
+    let new_action = NewAction {
+        id: action_id.clone(),
+        action_type: "advisory".to_string(),
+        parameters: Some(serde_json::json!({ "title": title, "link": link }).to_string()),
+        author: Some("advisory-ingestion".to_string()),
+        created_at: now,
+        expires_at,
+        canceled: false,
+    };
+
+    diesel::insert_into(actions::table)
+        .values(&new_action)
+        .execute(conn)?;
+
+    for device in matched_devices {
+        diesel::insert_into(action_targets::table)
+            .values((
+                action_targets::action_id.eq(&action_id),
+                action_targets::device_id.eq(&device.device_id),
+                action_targets::status.eq("pending"),
+                action_targets::last_update.eq(now),
+            ))
+            .execute(conn)?;
+    }
+
+    log_audit(
+        conn,
+        "advisory-ingestion",
+        "advisory_action_created",
+        Some(&action_id),
+        Some(title),
+    )?;
+
+    Ok(())
+}
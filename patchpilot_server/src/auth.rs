@@ -2,6 +2,9 @@ use rocket::request::{FromRequest, Outcome, Request};
 use rocket::http::Status;
 use rocket::State;
 use diesel::prelude::*;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rand::rngs::OsRng;
 use crate::db::DbPool;
 use crate::schema::{users, roles, user_roles};
 
@@ -17,6 +20,12 @@ pub struct AuthUser {
     pub id: i32,
     pub username: String,
     pub roles: Vec<UserRole>,
+    /// Mirrors `users.blocked`. A blocked account never makes it past
+    /// [`FromRequest::from_request`] below, so by the time any route
+    /// handler sees an `AuthUser` this is always `false` — kept on the
+    /// struct anyway so [`SettingsPrincipal`](crate::settings::SettingsPrincipal)
+    /// has something to carry forward instead of re-querying the DB.
+    pub blocked: bool,
 }
 
 #[rocket::async_trait]
@@ -33,27 +42,42 @@ impl<'r> FromRequest<'r> for AuthUser {
 
         if let Some(cookie) = cookie {
             if let Ok(user_id) = cookie.value().parse::<i32>() {
-                let mut conn = match pool.get() {
+                let conn = match pool.get().await {
                     Ok(c) => c,
                     Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
                 };
 
-                // Fetch username
-                let username_result = users::table
-                    .filter(users::id.eq(user_id))
-                    .select(users::username)
-                    .first::<String>(&mut conn)
-                    .optional()
+                let loaded = conn
+                    .interact(move |conn| {
+                        let user_result = users::table
+                            .filter(users::id.eq(user_id))
+                            .select((users::username, users::blocked))
+                            .first::<(String, bool)>(conn)
+                            .optional()
+                            .unwrap_or(None);
+
+                        user_result.map(|(username, blocked)| {
+                            let role_names = user_roles::table
+                                .inner_join(roles::table.on(roles::id.eq(user_roles::role_id)))
+                                .filter(user_roles::user_id.eq(user_id))
+                                .select(roles::name)
+                                .load::<String>(conn)
+                                .unwrap_or_else(|_| vec![]);
+
+                            (username, blocked, role_names)
+                        })
+                    })
+                    .await
                     .unwrap_or(None);
 
-                if let Some(username) = username_result {
-                    // Fetch roles
-                    let role_names = user_roles::table
-                        .inner_join(roles::table.on(roles::id.eq(user_roles::role_id)))
-                        .filter(user_roles::user_id.eq(user_id))
-                        .select(roles::name)
-                        .load::<String>(&mut conn)
-                        .unwrap_or_else(|_| vec![]);
+                if let Some((username, blocked, role_names)) = loaded {
+                    // A blocked account is refused here rather than let
+                    // through with an empty-looking principal — every
+                    // route gated on `AuthUser` should treat it the same
+                    // as a session that was never valid.
+                    if blocked {
+                        return Outcome::Failure((Status::Unauthorized, ()));
+                    }
 
                     let roles_vec = role_names
                         .into_iter()
@@ -68,6 +92,7 @@ impl<'r> FromRequest<'r> for AuthUser {
                         id: user_id,
                         username,
                         roles: roles_vec,
+                        blocked: false,
                     });
                 }
             }
@@ -82,3 +107,23 @@ impl AuthUser {
         self.roles.iter().any(|r| r == role)
     }
 }
+
+/// Hash a plaintext password with argon2 for storage in `users.password_hash`.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+/// Check a plaintext password against an argon2 hash produced by
+/// `hash_password`. Any parse failure (e.g. a stale bcrypt hash left over
+/// from before this switched to argon2) is treated as a non-match rather
+/// than an error, same as a wrong password.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,179 @@
+//! Supervised background-worker runtime.
+//!
+//! Replaces the ad-hoc `tokio::spawn` loops previously scattered across
+//! `tasks/` and the (now-retired) ignite fairings: each worker is spawned
+//! with a name and a [`CancellationToken`] child of the runner's root
+//! token, so a single [`BackgroundRunner::shutdown`] cancels every worker
+//! at once. [`BackgroundRunner::register`] builds on top of
+//! [`BackgroundRunner::spawn_worker`] for the common case of a periodic
+//! tick: a failed or panicked tick is logged and retried with exponential
+//! backoff (capped at [`MAX_BACKOFF`]) instead of silently taking the loop
+//! down. `shutdown` waits for every worker to notice cancellation and
+//! return, up to [`SHUTDOWN_TIMEOUT`], logging (not blocking forever on)
+//! any that don't.
+use rocket::tokio::task::JoinHandle;
+use rocket::tokio::{self, select};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+pub type WorkerError = Box<dyn std::error::Error + Send + Sync>;
+
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How long `shutdown` waits for workers to notice cancellation and exit
+/// before giving up on them and returning anyway.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct BackgroundRunner {
+    token: CancellationToken,
+    handles: Mutex<Vec<(String, JoinHandle<()>)>>,
+}
+
+impl BackgroundRunner {
+    pub fn new() -> Self {
+        Self {
+            token: CancellationToken::new(),
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Spawn a long-lived worker that owns its own loop. `f` is handed a
+    /// token cancelled when [`shutdown`](Self::shutdown) is called — the
+    /// body should `select!` on `token.cancelled()` (or poll
+    /// `token.is_cancelled()` each iteration) and return promptly rather
+    /// than looping forever.
+    pub fn spawn_worker<F, Fut>(&self, name: &str, f: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let token = self.token.child_token();
+        let handle = tokio::spawn(f(token));
+        self.handles.lock().unwrap().push((name.to_string(), handle));
+    }
+
+    /// Register a worker that runs `work` every `interval` until the
+    /// runner is shut down.
+    pub fn register<F, Fut>(&self, name: &str, interval: Duration, mut work: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), WorkerError>> + Send + 'static,
+    {
+        let worker_name = name.to_string();
+        self.spawn_worker(name, move |token| async move {
+            let mut backoff = Duration::from_secs(1);
+
+            loop {
+                select! {
+                    _ = token.cancelled() => {
+                        tracing::info!("Worker '{}' shutting down", worker_name);
+                        return;
+                    }
+                    _ = tokio::time::sleep(interval) => {
+                        if !run_tick(&worker_name, work(), &mut backoff).await {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Like [`register`](Self::register), but the sleep interval before
+    /// each tick is recomputed from `subscription`'s latest snapshot
+    /// instead of being fixed at registration time. `interval_fn` maps the
+    /// current settings snapshot to the delay until the next tick, so
+    /// e.g. a changed `auto_refresh_seconds` takes effect on the very next
+    /// sleep rather than requiring a restart — see
+    /// `settings::ServerSettings::subscribe`.
+    pub fn register_dynamic<F, Fut>(
+        &self,
+        name: &str,
+        subscription: crate::settings::SettingsSubscription,
+        interval_fn: impl Fn(&crate::settings::ServerSettings) -> Duration + Send + 'static,
+        mut work: F,
+    ) where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), WorkerError>> + Send + 'static,
+    {
+        let worker_name = name.to_string();
+        self.spawn_worker(name, move |token| async move {
+            let mut backoff = Duration::from_secs(1);
+            let mut snapshot = subscription.snapshot();
+
+            loop {
+                if subscription.poll_dirty() {
+                    snapshot = subscription.snapshot();
+                }
+                let interval = interval_fn(&snapshot);
+
+                select! {
+                    _ = token.cancelled() => {
+                        tracing::info!("Worker '{}' shutting down", worker_name);
+                        return;
+                    }
+                    _ = tokio::time::sleep(interval) => {
+                        if !run_tick(&worker_name, work(), &mut backoff).await {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Signal every worker to stop and wait (up to [`SHUTDOWN_TIMEOUT`])
+    /// for them all to finish.
+    pub async fn shutdown(&self) {
+        self.token.cancel();
+
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        for (name, handle) in handles {
+            match tokio::time::timeout(SHUTDOWN_TIMEOUT, handle).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Worker '{}' did not shut down cleanly: {}", name, e),
+                Err(_) => tracing::warn!("Worker '{}' did not shut down within {:?}", name, SHUTDOWN_TIMEOUT),
+            }
+        }
+    }
+}
+
+impl Default for BackgroundRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run one tick's work with the shared failed/panicked backoff handling
+/// used by both [`BackgroundRunner::register`] and
+/// [`BackgroundRunner::register_dynamic`]. Returns `false` if the worker
+/// should stop (its task was cancelled out from under it).
+async fn run_tick<Fut>(worker_name: &str, fut: Fut, backoff: &mut Duration) -> bool
+where
+    Fut: Future<Output = Result<(), WorkerError>> + Send + 'static,
+{
+    match tokio::spawn(fut).await {
+        Ok(Ok(())) => {
+            *backoff = Duration::from_secs(1);
+            true
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("Worker '{}' failed: {}; retrying in {:?}", worker_name, e, backoff);
+            tokio::time::sleep(*backoff).await;
+            *backoff = (*backoff * 2).min(MAX_BACKOFF);
+            true
+        }
+        Err(join_err) if join_err.is_panic() => {
+            tracing::error!("Worker '{}' panicked; retrying in {:?}", worker_name, backoff);
+            tokio::time::sleep(*backoff).await;
+            *backoff = (*backoff * 2).min(MAX_BACKOFF);
+            true
+        }
+        Err(join_err) => {
+            tracing::warn!("Worker '{}' task cancelled: {}", worker_name, join_err);
+            false
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//! Generated Cap'n Proto bindings for `schemas/*.capnp`, emitted into
+//! `OUT_DIR` by `build.rs` at compile time. Nothing here is hand-written —
+//! edit the `.capnp` source instead and rebuild.
+#![allow(dead_code, clippy::all)]
+
+pub mod device_report_capnp {
+    include!(concat!(env!("OUT_DIR"), "/device_report_capnp.rs"));
+}
+
+pub mod control_capnp {
+    include!(concat!(env!("OUT_DIR"), "/control_capnp.rs"));
+}
@@ -0,0 +1,115 @@
+//! Bearer-token authentication for devices, mirroring `token_auth`'s
+//! user-facing access/refresh pair but scoped to a `device_id` instead of a
+//! user account. A device earns its first refresh token when an operator
+//! approves it (see `routes::devices::heartbeat`), exchanges it for a
+//! short-lived access token via `/api/token`, and attaches that access
+//! token to every subsequent heartbeat — a bare `device_id` in the payload
+//! is no longer sufficient to act as that device.
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::state::AppState;
+
+/// How long an issued device access token is valid for.
+pub const DEVICE_ACCESS_TOKEN_TTL_SECS: i64 = 15 * 60;
+
+/// Claims embedded in a device access token.
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    device_id: String,
+    exp: usize,
+}
+
+/// Sign a new access token for `device_id_val`, expiring
+/// `DEVICE_ACCESS_TOKEN_TTL_SECS` from now. Signed with the same HMAC
+/// secret as `token_auth`'s user-facing access tokens (`ServerSettings::jwt_secret`).
+pub fn issue_device_access_token(
+    device_id_val: &str,
+    app_state: &AppState,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let jwt_secret = app_state.settings.read().unwrap().jwt_secret.clone();
+    let claims = Claims {
+        device_id: device_id_val.to_string(),
+        exp: (Utc::now() + Duration::seconds(DEVICE_ACCESS_TOKEN_TTL_SECS)).timestamp() as usize,
+    };
+
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )
+}
+
+/// Generate a fresh opaque device refresh token: 64 random bytes,
+/// URL-safe base64-encoded. Only its hash (see `hash_device_refresh_token`)
+/// is ever persisted — the raw value is handed to the agent once.
+pub fn generate_device_refresh_token() -> String {
+    use base64::Engine;
+
+    let mut bytes = [0u8; 64];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a device refresh token for storage/lookup.
+pub fn hash_device_refresh_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// An authenticated device, extracted from a validated
+/// `Authorization: Bearer <jwt>` header issued by `/api/token`.
+#[derive(Debug, Clone)]
+pub struct DeviceAuth {
+    pub device_id: String,
+}
+
+/// Validate a raw device access token and return the `device_id` it was
+/// issued for. Shared by the `FromRequest` guard below (for the JSON/HTTP
+/// routes) and `rpc::ControlImpl::report` (for the capnp control channel),
+/// so both channels enforce the exact same token.
+pub fn validate_device_access_token(token: &str, app_state: &AppState) -> Option<String> {
+    let jwt_secret = app_state.settings.read().unwrap().jwt_secret.clone();
+
+    let claims = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    );
+
+    claims.ok().map(|data| data.claims.device_id)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DeviceAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        let Some(token) = token else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+
+        let Outcome::Success(app_state) = req.guard::<&State<Arc<AppState>>>().await else {
+            return Outcome::Failure((Status::InternalServerError, ()));
+        };
+
+        let Some(device_id) = validate_device_access_token(token, &app_state) else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+
+        Outcome::Success(DeviceAuth { device_id })
+    }
+}
@@ -1,66 +1,187 @@
-#[macro_use]
-extern crate rocket;
-
-mod db;
-mod routes;
-mod tasks;
-mod models;
-mod schema;
-mod settings;
-mod auth;
-mod state;
-
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use sysinfo::System;
-use log::info;
-
-use rocket::fs::FileServer;
-
-use crate::db::{initialize, get_conn, create_default_admin, DbPool};
-use crate::tasks::{spawn_action_ttl_sweeper, spawn_pending_cleanup};
-use crate::state::{AppState, SystemState};
-
-#[launch]
-fn rocket() -> _ {
-    // 1️⃣ Initialize DB + logging
-    let pool: DbPool = initialize();
-
-    // 2️⃣ Ensure default admin exists
-    {
-        let mut conn = get_conn(&pool);
-        create_default_admin(&mut conn);
-    }
-
-    // 3️⃣ Spawn action TTL sweeper (background task)
-    spawn_action_ttl_sweeper(pool.clone());
-
-    // 4️⃣ Build SystemState
-    let system_state = SystemState {
-        db_pool: pool.clone(),
-        system: Arc::new(Mutex::new(System::new_all())),
-    };
-
-    // 5️⃣ Build AppState
-    let app_state = Arc::new(AppState {
-        system: Arc::new(system_state),
-        pending_devices: Arc::new(Mutex::new(HashMap::new())),
-        settings: Arc::new(Mutex::new(settings::ServerSettings::load())),
-    });
-
-    // 6️⃣ Spawn pending device cleanup task
-    spawn_pending_cleanup(app_state.clone());
-
-    info!("PatchPilot server ready");
-
-    // 7️⃣ Build Rocket
-    rocket::build()
-        .manage(pool)          // DB pool
-        .manage(app_state)     // AppState
-        .mount("/api", routes::api_routes())
-        .mount("/", routes::page_routes())
-        .mount("/auth", routes::auth_routes())
-        .mount("/users-groups", routes::users_groups_routes())
-        .mount("/roles", routes::roles_routes())
-        .mount("/static", FileServer::from("/opt/patchpilot_server/static"))
-}
+#[macro_use]
+extern crate rocket;
+
+mod advisories;
+mod background;
+mod db;
+mod routes;
+mod tasks;
+mod models;
+mod schema;
+mod settings;
+mod auth;
+mod token_auth;
+mod device_auth;
+mod opaque_auth;
+mod state;
+mod relay;
+mod tracing_setup;
+mod request_trace;
+mod openapi;
+mod storage;
+mod capnp_schema;
+mod rpc;
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::AtomicBool;
+use std::collections::HashMap;
+use sysinfo::System;
+use tracing::info;
+
+use rocket::fs::FileServer;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::background::BackgroundRunner;
+use crate::db::{initialize, bootstrap_connection, create_default_admin, DbPool};
+use crate::openapi::ApiDoc;
+use crate::request_trace::RequestTracing;
+use crate::tasks::{spawn_action_ttl_sweeper, spawn_monitor_scanner, spawn_pending_cleanup};
+use crate::state::{AppState, SystemState};
+
+/// Drains every registered [`BackgroundRunner`] worker once Rocket starts
+/// shutting down, so maintenance loops don't get killed mid-tick.
+struct BackgroundShutdown(Arc<BackgroundRunner>);
+
+#[rocket::async_trait]
+impl rocket::fairing::Fairing for BackgroundShutdown {
+    fn info(&self) -> rocket::fairing::Info {
+        rocket::fairing::Info {
+            name: "Background Worker Shutdown",
+            kind: rocket::fairing::Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &rocket::Rocket<rocket::Orbit>) {
+        let shutdown = rocket.shutdown();
+        let runner = self.0.clone();
+        rocket::tokio::spawn(async move {
+            shutdown.await;
+            runner.shutdown().await;
+        });
+    }
+}
+
+#[launch]
+fn rocket() -> _ {
+    // 1️⃣ Install the global tracing subscriber before anything else can log.
+    // Leaked rather than threaded through state — it only needs to outlive
+    // the process, and there's nowhere natural to stash a drop guard in a
+    // `#[launch]` fn that returns a `Rocket<Build>` rather than running the
+    // server itself.
+    Box::leak(Box::new(tracing_setup::init()));
+
+    // 2️⃣ Initialize DB
+    let pool: DbPool = initialize();
+
+    // 3️⃣ Ensure default admin exists
+    {
+        let mut conn = bootstrap_connection();
+        create_default_admin(&mut conn);
+    }
+
+    // 4️⃣ Load settings and wrap them in the shared `RwLock` + dirty flag
+    // that every `ServerSettings` mutator publishes to, so background
+    // loops registered below can subscribe before `AppState` even exists.
+    let loaded_settings = {
+        let mut conn = bootstrap_connection();
+        settings::ServerSettings::load(&mut conn)
+    };
+    let object_store = storage::build_object_store(&loaded_settings);
+    let settings_shared = Arc::new(RwLock::new(loaded_settings));
+    let settings_dirty = Arc::new(AtomicBool::new(false));
+
+    // 5️⃣ Start the supervised background-worker runtime and register the
+    // action TTL sweeper with it (replaces the old bare `tokio::spawn`).
+    let background = Arc::new(BackgroundRunner::new());
+    spawn_action_ttl_sweeper(
+        &background,
+        pool.clone(),
+        settings::ServerSettings::subscribe(settings_shared.clone(), settings_dirty.clone()),
+    );
+
+    // 6️⃣ Build SystemState
+    let system_state = SystemState {
+        db_pool: pool.clone(),
+        system: Arc::new(Mutex::new(System::new_all())),
+    };
+
+    // 7️⃣ Build AppState
+    let app_state = Arc::new(AppState {
+        system: Arc::new(system_state),
+        pending_devices: Arc::new(RwLock::new(HashMap::new())),
+        settings: settings_shared,
+        settings_dirty,
+        relay: Arc::new(relay::RelayRegistry::new()),
+        storage: object_store,
+    });
+
+    // 8️⃣ Register the pending device cleanup worker with the same runner
+    spawn_pending_cleanup(&background, app_state.clone());
+
+    // 9️⃣ Register the multi-target connectivity monitor with the same
+    // runner, so it reschedules against `auto_refresh_seconds` the same
+    // way the cleanup worker above does
+    spawn_monitor_scanner(
+        &background,
+        pool.clone(),
+        settings::ServerSettings::subscribe(app_state.settings.clone(), app_state.settings_dirty.clone()),
+    );
+
+    // 🔟 Register security-advisory feed ingestion with the same runner
+    advisories::spawn_advisory_ingestion(&background, pool.clone(), app_state.clone());
+
+    // 1️⃣1️⃣ Start the Cap'n Proto control channel (schemas/control.capnp) as a
+    // long-lived worker alongside the periodic ones, so BackgroundShutdown
+    // drains it the same way on shutdown. A no-op unless the operator has
+    // opted in with PATCHPILOT_RPC_ENABLED (see rpc::enabled).
+    {
+        let rpc_pool = pool.clone();
+        let rpc_app_state = app_state.clone();
+        background.spawn_worker("capnp_control_channel", move |token| {
+            rpc::serve(rpc_pool, rpc_app_state, token)
+        });
+    }
+
+    // 1️⃣2️⃣ Queue for interactive shell session control frames
+    let shell_queue = Arc::new(routes::shell::ShellControlQueue::new());
+
+    // 1️⃣3️⃣ Registry for live log-tail viewers
+    let log_tail_registry = Arc::new(routes::logs::LogTailRegistry::new());
+
+    // 1️⃣4️⃣ In-flight OPAQUE login sessions (between /login/start and /login/finish)
+    let opaque_login_sessions = Arc::new(opaque_auth::OpaqueLoginSessions::new());
+    let opaque_server_setup = Arc::new(opaque_auth::server_setup());
+
+    info!("PatchPilot server ready");
+
+    // 1️⃣5️⃣ Build Rocket
+    rocket::build()
+        .manage(pool)          // DB pool
+        .manage(app_state)     // AppState
+        .manage(shell_queue)   // Interactive shell session control queue
+        .manage(log_tail_registry) // Live log-tail viewer registry
+        .manage(opaque_login_sessions) // In-flight OPAQUE login handshakes
+        .manage(opaque_server_setup)   // This server's long-term OPAQUE keypair
+        .attach(RequestTracing)
+        .attach(BackgroundShutdown(background))
+        // `/api/v1` is the versioned namespace new agent builds should target;
+        // `/api` stays mounted unversioned on the same route set so agents
+        // already deployed in the field keep working against it. When the
+        // payload shape needs to change incompatibly, add a `routes::api_v2`
+        // module and mount it at `/api/v2` alongside this one rather than
+        // changing what `/api/v1` serves out from under old agents.
+        .mount("/api", routes::api_routes())
+        .mount("/api/v1", routes::api_routes())
+        .mount("/", routes::page_routes())
+        .mount("/auth", routes::auth_routes())
+        .mount("/users-groups", routes::users_groups_routes())
+        .mount("/roles", routes::roles_routes())
+        .mount("/static", FileServer::from("/opt/patchpilot_server/static"))
+        // Browsable API docs for the users/groups admin surface, generated
+        // from the handlers/forms themselves via `openapi::ApiDoc`.
+        .mount(
+            "/api-docs",
+            SwaggerUi::new("/api-docs/<_..>").url("/api-docs/openapi.json", ApiDoc::openapi()),
+        )
+}
@@ -8,6 +8,13 @@ use crate::schema::{
     action_targets,
     history_log,
     audit,
+    advisories,
+    refresh_tokens,
+    auth_requests,
+    nonces,
+    latency_history,
+    ping_targets,
+    monitor_results,
 };
 
 // Devices
@@ -38,15 +45,55 @@ pub struct Device {
 
     pub network_throughput: i64,
 
-    pub device_type: String,
+    /// Round-trip TCP latency to the server, as measured by the agent
+    /// itself on its most recent heartbeat (see `routes::devices::heartbeat`).
+    /// `None` until the first heartbeat that carries it.
+    pub ping_latency: Option<f32>,
+    /// JSON-serialized `Vec<ProbeResult>` — the agent's configured
+    /// reachability probes and their latest readings. Stored pre-serialized
+    /// like `network_interfaces` rather than as a relational table, since
+    /// only the latest snapshot matters here (trend over time lives in
+    /// `latency_history` instead).
+    pub probe_results: Option<String>,
+
+    /// Coarse platform, stored as [`DeviceType::to_i32`] rather than the
+    /// free-form string an earlier schema version used, so dashboard
+    /// filtering and action dispatch (see `routes::actions::submit_action`)
+    /// can match on it reliably. Use [`Device::device_type`] to get the enum
+    /// back.
+    pub device_type: i32,
     pub device_model: String,
     pub uptime: Option<String>,
     pub updates_available: bool,
 
     pub network_interfaces: Option<String>,
     pub ip_address: Option<String>,
+
+    pub protocol_version: Option<i32>,
+    pub capabilities: Option<String>,
+    pub protocol_outdated: bool,
+
+    /// Public half of the Ed25519 key the agent generated on first
+    /// registration. Set once, trust-on-first-use, by `routes::devices`'s
+    /// heartbeat handler; every heartbeat after that must carry a signature
+    /// verifying against this key, so a bare `device_id` is no longer
+    /// enough to impersonate the device.
+    pub public_key: Option<String>,
+
+    /// SHA-256 hash of the device's current opaque refresh token (see
+    /// `device_auth`), or `None` if it hasn't been issued one yet (not
+    /// approved) or an operator has revoked it. Only the hash is ever
+    /// stored — the raw token is handed to the agent once and never again.
+    pub refresh_token_hash: Option<String>,
 }
 
+// `protocol_version`/`capabilities`/`protocol_outdated`/`public_key`/
+// `refresh_token_hash` are deliberately left out of `NewDevice`: it's
+// upserted wholesale by `register_or_update_device` on every system-info
+// report, and an `AsChangeset` field would stomp the heartbeat's negotiated
+// values (or the pinned identity key, or an issued session token) back to
+// `None`/`false` on the next unrelated report. The heartbeat route updates
+// those columns directly instead.
 #[derive(Insertable, AsChangeset)]
 #[diesel(table_name = devices)]
 pub struct NewDevice {
@@ -70,8 +117,10 @@ pub struct NewDevice {
     pub disk_health: String,
 
     pub network_throughput: i64,
+    pub ping_latency: Option<f32>,
+    pub probe_results: Option<String>,
 
-    pub device_type: String,
+    pub device_type: i32,
     pub device_model: String,
     pub uptime: Option<String>,
     pub updates_available: bool,
@@ -82,6 +131,15 @@ pub struct NewDevice {
 
 // System Payloads
 
+/// One configured probe target's latest reachability reading, as reported
+/// by the agent (see the client's own `ProbeResult`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProbeResult {
+    pub target: String,
+    pub ping_ms: Option<f32>,
+    pub up: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SystemInfo {
     pub os_name: String,
@@ -102,16 +160,103 @@ pub struct SystemInfo {
 
     pub network_interfaces: Option<String>,
     pub ip_address: Option<String>,
+
+    /// Seconds since the agent's host booted, as the agent's own `sysinfo`
+    /// query reported it. `#[serde(default)]` so an agent running an older
+    /// protocol version that doesn't send this field still deserializes.
+    #[serde(default)]
+    pub uptime_secs: i64,
+
+    /// Round-trip TCP latency the agent measured to this server, fresh on
+    /// every heartbeat.
+    pub server_latency_ms: Option<f32>,
+    /// The agent's configured reachability probes and their latest results.
+    #[serde(default)]
+    pub probe_results: Vec<ProbeResult>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub device_id: String,
     pub system_info: SystemInfo,
+    /// Free-form platform label as the agent reported it (e.g. an OS name
+    /// string). Parsed into a [`DeviceType`] via [`DeviceType::from_label`]
+    /// wherever a `Device`/`NewDevice` row is built from this — the wire
+    /// format stays a string so older agents don't need to know about the
+    /// enum, but nothing downstream trusts it as anything more than a hint.
     pub device_type: Option<String>,
     pub device_model: Option<String>,
 }
 
+/// Coarse device platform. Stored on `Device`/`NewDevice` as a small integer
+/// (see the migration that replaced the old free-form `device_type` text
+/// column) so dashboard filtering and action dispatch can match on it
+/// reliably instead of guessing at OS-name substrings every time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceType {
+    Unknown,
+    Windows,
+    MacOs,
+    Linux,
+    Bsd,
+}
+
+impl DeviceType {
+    pub fn from_i32(v: i32) -> Self {
+        match v {
+            1 => DeviceType::Windows,
+            2 => DeviceType::MacOs,
+            3 => DeviceType::Linux,
+            4 => DeviceType::Bsd,
+            _ => DeviceType::Unknown,
+        }
+    }
+
+    pub fn to_i32(self) -> i32 {
+        match self {
+            DeviceType::Unknown => 0,
+            DeviceType::Windows => 1,
+            DeviceType::MacOs => 2,
+            DeviceType::Linux => 3,
+            DeviceType::Bsd => 4,
+        }
+    }
+
+    /// Parse whatever free-form platform label an agent sent (see
+    /// `DeviceInfo::device_type`) into the enum. Matched loosely since
+    /// agents across OS versions spell these inconsistently ("Darwin" vs
+    /// "macOS", "Windows 10 Pro", etc) — anything unrecognized is
+    /// `Unknown` rather than rejected outright.
+    pub fn from_label(label: &str) -> Self {
+        let lower = label.to_lowercase();
+        if lower.contains("windows") {
+            DeviceType::Windows
+        } else if lower.contains("mac") || lower.contains("darwin") {
+            DeviceType::MacOs
+        } else if lower.contains("linux") {
+            DeviceType::Linux
+        } else if lower.contains("bsd") {
+            DeviceType::Bsd
+        } else {
+            DeviceType::Unknown
+        }
+    }
+
+    /// Whether this is a recognized desktop/server platform rather than
+    /// `Unknown` — used to skip platform-dependent handling for devices
+    /// whose OS we couldn't identify at all.
+    pub fn is_desktop(self) -> bool {
+        !matches!(self, DeviceType::Unknown)
+    }
+
+    /// Whether a `self_update` action is expected to work on this platform.
+    /// `submit_action` refuses to dispatch one to a device that fails this
+    /// rather than sending it and letting the agent fail the update.
+    pub fn supports_auto_update(self) -> bool {
+        matches!(self, DeviceType::Windows | DeviceType::MacOs | DeviceType::Linux)
+    }
+}
+
 // Actions
 
 #[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
@@ -127,7 +272,7 @@ pub struct Action {
     pub canceled: bool,
 }
 
-#[derive(Debug, Insertable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Insertable, Serialize, Deserialize)]
 #[diesel(table_name = actions)]
 pub struct NewAction {
     pub id: String,
@@ -151,6 +296,34 @@ pub struct ActionTarget {
     pub response: Option<String>,
 }
 
+// Advisories
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = advisories)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Advisory {
+    pub id: i32,
+    pub guid: String,
+    pub source_url: String,
+    pub title: String,
+    pub link: String,
+    pub os_match: Option<String>,
+    pub published_at: NaiveDateTime,
+    pub ingested_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = advisories)]
+pub struct NewAdvisory {
+    pub guid: String,
+    pub source_url: String,
+    pub title: String,
+    pub link: String,
+    pub os_match: Option<String>,
+    pub published_at: NaiveDateTime,
+    pub ingested_at: NaiveDateTime,
+}
+
 // History
 
 #[derive(Debug, Queryable, Selectable, Serialize)]
@@ -166,6 +339,36 @@ pub struct HistoryLog {
     pub created_at: NaiveDateTime,
 }
 
+#[derive(Debug, Insertable)]
+#[diesel(table_name = history_log)]
+pub struct NewHistoryRecord {
+    pub action_id: Option<String>,
+    pub device_name: Option<String>,
+    pub actor: Option<String>,
+    pub action_type: String,
+    pub details: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl NewHistoryRecord {
+    pub fn new(
+        action_id: Option<String>,
+        device_name: Option<String>,
+        actor: Option<String>,
+        action_type: String,
+        details: Option<String>,
+    ) -> Self {
+        Self {
+            action_id,
+            device_name,
+            actor,
+            action_type,
+            details,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
 // Audit
 
 #[derive(Debug, Queryable, Insertable, Selectable, Serialize, Deserialize)]
@@ -178,6 +381,157 @@ pub struct AuditLog {
     pub target: Option<String>,
     pub details: Option<String>,
     pub created_at: NaiveDateTime,
+    /// `entry_hash` of the row immediately before this one in `id` order
+    /// (the fixed genesis hash for the very first row). See
+    /// `db::verify_audit_chain`.
+    pub prev_hash: String,
+    /// SHA-256 of `prev_hash` plus this row's own fields; recomputing and
+    /// comparing this chain is how `db::verify_audit_chain` detects a
+    /// tampered or deleted row.
+    pub entry_hash: String,
+}
+
+// Auth requests (device-approval handshake — see routes::auth_request)
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = auth_requests)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AuthRequest {
+    pub id: String,
+    pub device_id: String,
+    pub request_ip: Option<String>,
+    pub public_key: String,
+    pub access_code: String,
+    pub approved: Option<bool>,
+    pub encrypted_secret: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub response_date: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = auth_requests)]
+pub struct NewAuthRequest {
+    pub id: String,
+    pub device_id: String,
+    pub request_ip: Option<String>,
+    pub public_key: String,
+    pub access_code: String,
+    pub approved: Option<bool>,
+    pub encrypted_secret: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub response_date: Option<NaiveDateTime>,
+}
+
+// Refresh tokens
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = refresh_tokens)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub issued_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked: bool,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken {
+    pub user_id: i32,
+    pub token_hash: String,
+    pub issued_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked: bool,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+// Nonces (heartbeat replay protection — see routes::devices)
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = nonces)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Nonce {
+    pub id: i32,
+    pub nonce: String,
+    pub issued_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub consumed: bool,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = nonces)]
+pub struct NewNonce {
+    pub nonce: String,
+    pub issued_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub consumed: bool,
+}
+
+// Latency history (per-heartbeat server round-trip samples — see
+// routes::devices::latency_history)
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = latency_history)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct LatencySample {
+    pub id: i32,
+    pub device_id: String,
+    pub server_latency_ms: Option<f32>,
+    pub recorded_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = latency_history)]
+pub struct NewLatencySample {
+    pub device_id: String,
+    pub server_latency_ms: Option<f32>,
+    pub recorded_at: NaiveDateTime,
+}
+
+// Connectivity monitoring (multi-target ping sweep replacing the old
+// single `ServerSettings::ping_target_ip` — see tasks::monitor_scan)
+
+#[derive(Debug, Queryable, Identifiable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = ping_targets)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PingTarget {
+    pub id: i32,
+    pub name: String,
+    pub address: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = ping_targets)]
+pub struct NewPingTarget {
+    pub name: String,
+    pub address: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = monitor_results)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct MonitorResult {
+    pub id: i32,
+    pub target_id: i32,
+    pub recorded_at: NaiveDateTime,
+    pub ipv4_reachable: Option<bool>,
+    pub ipv6_reachable: Option<bool>,
+    pub rtt_ms: Option<f32>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = monitor_results)]
+pub struct NewMonitorResult {
+    pub target_id: i32,
+    pub recorded_at: NaiveDateTime,
+    pub ipv4_reachable: Option<bool>,
+    pub ipv6_reachable: Option<bool>,
+    pub rtt_ms: Option<f32>,
 }
 
 // Device Logic
@@ -207,6 +561,8 @@ impl DeviceInfo {
         s.disk_total = o.disk_total;
         s.disk_free  = o.disk_free;
         s.network_throughput = o.network_throughput;
+        s.server_latency_ms = o.server_latency_ms;
+        s.probe_results = o.probe_results.clone();
 
         if let Some(t) = &other.device_type {
             if !t.is_empty() { self.device_type = Some(t.clone()); }
@@ -247,19 +603,45 @@ impl DeviceInfo {
             disk_health: s.disk_health.clone(),
 
             network_throughput: s.network_throughput,
+            ping_latency: s.server_latency_ms,
+            probe_results: serde_json::to_string(&s.probe_results).ok(),
 
-            device_type: self.device_type.clone().unwrap_or_default(),
+            device_type: DeviceType::from_label(self.device_type.as_deref().unwrap_or_default()).to_i32(),
             device_model: self.device_model.clone().unwrap_or_default(),
 
-            uptime: Some("0h 0m".into()),
+            uptime: Some(format_uptime_secs(s.uptime_secs)),
             updates_available: false,
 
             network_interfaces: s.network_interfaces.clone(),
             ip_address: s.ip_address.clone(),
+
+            protocol_version: None,
+            capabilities: None,
+            protocol_outdated: false,
+            public_key: None,
+            refresh_token_hash: None,
         }
     }
 }
 
+impl Device {
+    /// Whether this device's last-negotiated capability set includes `cap`.
+    /// Devices that have never completed the heartbeat handshake (no
+    /// capabilities recorded yet) are treated as not supporting anything.
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.capabilities
+            .as_deref()
+            .map(|caps| caps.split(',').any(|c| c == cap))
+            .unwrap_or(false)
+    }
+
+    /// This device's platform, decoded from the stored integer. See
+    /// [`DeviceType`].
+    pub fn device_type(&self) -> DeviceType {
+        DeviceType::from_i32(self.device_type)
+    }
+}
+
 impl NewDevice {
     pub fn from_device_info(device_id: &str, info: &DeviceInfo, existing: Option<&Device>) -> Self {
         let s = &info.system_info;
@@ -284,11 +666,13 @@ impl NewDevice {
             disk_health: s.disk_health.clone(),
 
             network_throughput: s.network_throughput,
+            ping_latency: s.server_latency_ms,
+            probe_results: serde_json::to_string(&s.probe_results).ok(),
 
-            device_type: info.device_type.clone().unwrap_or_default(),
+            device_type: DeviceType::from_label(info.device_type.as_deref().unwrap_or_default()).to_i32(),
             device_model: info.device_model.clone().unwrap_or_default(),
 
-            uptime: Some("0h 0m".into()),
+            uptime: Some(format_uptime_secs(s.uptime_secs)),
             updates_available: false,
 
             network_interfaces: s.network_interfaces.clone(),
@@ -297,6 +681,14 @@ impl NewDevice {
     }
 }
 
+/// Render a `SystemInfo::uptime_secs` reading (time since the agent's host
+/// booted) the same way `Device::compute_uptime` renders its duration, so
+/// the dashboard shows one consistent format regardless of which path set
+/// the column.
+fn format_uptime_secs(secs: i64) -> String {
+    format!("{}h {}m", secs / 3600, (secs / 60) % 60)
+}
+
 impl Device {
     pub fn compute_uptime(&self) -> String {
         let duration = Utc::now().naive_utc() - self.last_checkin;
@@ -305,6 +697,11 @@ impl Device {
 
     pub fn enrich_for_dashboard(mut self) -> Self {
         self.uptime = Some(self.compute_uptime());
+        // Re-validate rather than trust the stored value as-is: a row
+        // written before `DeviceType` existed (or patched directly in the
+        // DB) could hold an out-of-range code, which would otherwise look
+        // like a platform the dashboard doesn't recognize at all.
+        self.device_type = self.device_type().to_i32();
         self
     }
 
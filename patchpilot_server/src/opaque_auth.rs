@@ -0,0 +1,208 @@
+//! OPAQUE (asymmetric PAKE) login for the dashboard, so a plaintext
+//! password never transits the wire or touches server memory — only an
+//! OPRF-blinded value does. This replaces `/login`'s argon2 `verify`
+//! check with a two-round `/login/start` + `/login/finish` exchange (see
+//! `routes::auth`). `users.opaque_password_file` holds each user's OPAQUE
+//! password file; `users.password_hash` is kept around only so a user who
+//! hasn't completed the handshake client-side yet can still log in the
+//! old way, which transparently re-enrolls them (see `enroll_from_plaintext`)
+//! so their next login goes through OPAQUE instead.
+use dashmap::DashMap;
+use opaque_ke::{
+    CipherSuite, ClientRegistrationFinishParameters, CredentialFinalization, CredentialRequest,
+    ServerLogin, ServerLoginStartParameters, ServerRegistration, ServerSetup,
+};
+use rand::rngs::OsRng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::time::{Duration, Instant};
+
+/// The concrete OPAQUE instantiation this server speaks: ristretto255 for
+/// both the OPRF and the key exchange group, triple-DH for the key
+/// exchange itself, and argon2 as the password-stretching function so a
+/// stolen password file is at least as hard to brute-force offline as our
+/// previous argon2 `password_hash` column was.
+pub struct PatchPilotCipherSuite;
+
+/// This server's long-term OPAQUE keypair, Rocket-managed alongside
+/// `OpaqueLoginSessions` — see `server_setup`.
+pub type PatchPilotServerSetup = ServerSetup<PatchPilotCipherSuite>;
+
+impl CipherSuite for PatchPilotCipherSuite {
+    type OprfCs = opaque_ke::Ristretto255;
+    type KeGroup = opaque_ke::Ristretto255;
+    type KeyExchange = opaque_ke::key_exchange::tripledh::TripleDh;
+    type Ksf = argon2::Argon2<'static>;
+}
+
+/// Rebuild this server's long-term OPAQUE keypair deterministically from
+/// `OPAQUE_SERVER_SEED`, same pattern as the `JWT_SECRET` fallback in
+/// `ServerSettings::default` — a fixed
+/// dev default so the server still boots without extra setup, but any real
+/// deployment must set this. Unlike the JWT secret this *must* stay stable
+/// across restarts: every stored `opaque_password_file` was produced
+/// against this keypair, and rotating it invalidates all of them at once.
+pub fn server_setup() -> ServerSetup<PatchPilotCipherSuite> {
+    let seed = env::var("OPAQUE_SERVER_SEED")
+        .unwrap_or_else(|_| "dev-insecure-opaque-seed-change-me".to_string());
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_bytes());
+    let seed_bytes: [u8; 32] = hasher.finalize().into();
+    let mut rng = ChaCha20Rng::from_seed(seed_bytes);
+    ServerSetup::<PatchPilotCipherSuite>::new(&mut rng)
+}
+
+/// Bootstrap a user straight into an OPAQUE password file from a password
+/// the server briefly holds in memory — used only right after a successful
+/// legacy argon2 `/login` check, where the server already saw the
+/// plaintext anyway. Runs both halves of the OPAQUE registration handshake
+/// locally instead of requiring a second client round trip, since there's
+/// no new secret being established here that the server doesn't already
+/// know.
+pub fn enroll_from_plaintext(
+    setup: &ServerSetup<PatchPilotCipherSuite>,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<Vec<u8>> {
+    use opaque_ke::ClientRegistration;
+
+    let mut rng = OsRng;
+    let client_start = ClientRegistration::<PatchPilotCipherSuite>::start(&mut rng, password.as_bytes())
+        .map_err(|e| anyhow::anyhow!("OPAQUE registration start failed: {:?}", e))?;
+
+    let server_start = ServerRegistration::<PatchPilotCipherSuite>::start(
+        setup,
+        client_start.message,
+        username.as_bytes(),
+    )
+    .map_err(|e| anyhow::anyhow!("OPAQUE registration server start failed: {:?}", e))?;
+
+    let client_finish = client_start
+        .state
+        .finish(
+            &mut rng,
+            password.as_bytes(),
+            server_start.message,
+            ClientRegistrationFinishParameters::default(),
+        )
+        .map_err(|e| anyhow::anyhow!("OPAQUE registration finish failed: {:?}", e))?;
+
+    let password_file = ServerRegistration::<PatchPilotCipherSuite>::finish(client_finish.message);
+    Ok(password_file.serialize().to_vec())
+}
+
+/// Start a login: given the user's stored password file (`None` if they
+/// haven't been enrolled yet) and the client's blinded credential request,
+/// derive the server's credential response plus the login state
+/// `/login/finish` needs to complete the exchange. `ServerLogin::start`
+/// handles the "no such user" case itself via `password_file: None` so a
+/// login attempt against an unenrolled or nonexistent username looks the
+/// same to the client either way.
+pub fn login_start(
+    setup: &ServerSetup<PatchPilotCipherSuite>,
+    password_file: Option<Vec<u8>>,
+    username: &str,
+    credential_request_bytes: &[u8],
+) -> anyhow::Result<(ServerLogin<PatchPilotCipherSuite>, Vec<u8>)> {
+    let mut rng = OsRng;
+
+    let password_file = password_file
+        .map(|bytes| {
+            ServerRegistration::<PatchPilotCipherSuite>::deserialize(&bytes)
+                .map_err(|e| anyhow::anyhow!("Stored OPAQUE password file is corrupt: {:?}", e))
+        })
+        .transpose()?;
+
+    let credential_request = CredentialRequest::deserialize(credential_request_bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid OPAQUE credential request: {:?}", e))?;
+
+    let result = ServerLogin::<PatchPilotCipherSuite>::start(
+        &mut rng,
+        setup,
+        password_file,
+        credential_request,
+        username.as_bytes(),
+        ServerLoginStartParameters::default(),
+    )
+    .map_err(|e| anyhow::anyhow!("OPAQUE login start failed: {:?}", e))?;
+
+    Ok((result.state, result.message.serialize().to_vec()))
+}
+
+/// Finish a login previously started with `login_start`, verifying the
+/// client's credential finalization against the retained `state`. Success
+/// here is cryptographic proof the caller holds the password behind the
+/// stored OPAQUE file — the caller still needs to set the usual private
+/// `user_id` cookie afterward (see `routes::auth::login_finish`).
+pub fn login_finish(
+    state: ServerLogin<PatchPilotCipherSuite>,
+    credential_finalization_bytes: &[u8],
+) -> anyhow::Result<()> {
+    let finalization = CredentialFinalization::deserialize(credential_finalization_bytes)
+        .map_err(|e| anyhow::anyhow!("Invalid OPAQUE credential finalization: {:?}", e))?;
+
+    state
+        .finish(finalization)
+        .map_err(|e| anyhow::anyhow!("OPAQUE login finish failed: {:?}", e))?;
+
+    Ok(())
+}
+
+/// How long a `/login/start` session stays eligible for `/login/finish`
+/// before it's swept as abandoned — long enough for a slow round trip,
+/// short enough that a pile of never-finished attempts doesn't linger.
+const LOGIN_SESSION_TTL: Duration = Duration::from_secs(60);
+
+/// Server-held state between `/login/start` and `/login/finish`, keyed by
+/// a random session id handed to the client. Rocket-managed like
+/// `routes::shell::ShellControlQueue` — this is per-process ephemeral
+/// state, not something that belongs in the database.
+pub struct OpaqueLoginSessions {
+    pending: DashMap<String, (ServerLogin<PatchPilotCipherSuite>, String, Instant)>,
+}
+
+impl OpaqueLoginSessions {
+    pub fn new() -> Self {
+        Self {
+            pending: DashMap::new(),
+        }
+    }
+
+    /// Stash a started login (and the username it was started for, so
+    /// `take` can hand back who just authenticated) under a fresh session
+    /// id, evicting anything already expired so an endless stream of
+    /// started-but-abandoned logins doesn't grow this map forever.
+    pub fn insert(&self, state: ServerLogin<PatchPilotCipherSuite>, username: &str) -> String {
+        self.pending.retain(|_, (_, _, started)| started.elapsed() < LOGIN_SESSION_TTL);
+
+        use base64::Engine;
+        use rand::RngCore;
+        let mut id_bytes = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut id_bytes);
+        let session_id = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(id_bytes);
+
+        self.pending
+            .insert(session_id.clone(), (state, username.to_string(), Instant::now()));
+        session_id
+    }
+
+    /// Take (and remove) the login state and username for `session_id`, if
+    /// it exists and hasn't expired. Single-use: a session can only ever be
+    /// finished once.
+    pub fn take(&self, session_id: &str) -> Option<(ServerLogin<PatchPilotCipherSuite>, String)> {
+        let (_, (state, username, started)) = self.pending.remove(session_id)?;
+        if started.elapsed() < LOGIN_SESSION_TTL {
+            Some((state, username))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for OpaqueLoginSessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,23 @@
+//! Generated OpenAPI document for the users/groups/roles/auth surface,
+//! served as JSON at `/api-docs/openapi.json` and browsable via Swagger UI
+//! at `/api-docs` (see `main::rocket`). Keeping the schema derived from the
+//! handlers/forms themselves (rather than hand-written) means it can't
+//! drift the way a separately-maintained spec would.
+use utoipa::OpenApi;
+
+use crate::routes::users_groups::{AgentSystemInfo, GroupForm, UserForm};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::routes::users_groups::list_users_groups,
+        crate::routes::users_groups::add_user,
+        crate::routes::users_groups::add_group,
+        crate::routes::users_groups::delete_user,
+        crate::routes::users_groups::block_user,
+        crate::routes::users_groups::unblock_user,
+        crate::routes::users_groups::delete_group,
+    ),
+    components(schemas(UserForm, GroupForm, AgentSystemInfo))
+)]
+pub struct ApiDoc;
@@ -0,0 +1,50 @@
+//! Reverse relay: keeps one outbound channel per connected agent so the
+//! server can push actions the moment they're created instead of waiting
+//! for the agent's next heartbeat poll.
+use dashmap::DashMap;
+use rocket::tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde_json::Value;
+
+/// Registry of live agent relay channels, keyed by `device_id`.
+pub struct RelayRegistry {
+    channels: DashMap<String, UnboundedSender<Value>>,
+}
+
+impl RelayRegistry {
+    pub fn new() -> Self {
+        Self {
+            channels: DashMap::new(),
+        }
+    }
+
+    /// Called when an agent opens its long-lived relay connection. Returns
+    /// the receiving half the route handler should stream to the client.
+    pub fn register(&self, device_id: &str) -> UnboundedReceiver<Value> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.channels.insert(device_id.to_string(), tx);
+        rx
+    }
+
+    pub fn unregister(&self, device_id: &str) {
+        self.channels.remove(device_id);
+    }
+
+    pub fn is_connected(&self, device_id: &str) -> bool {
+        self.channels.contains_key(device_id)
+    }
+
+    /// Fan an action out to the device's live channel, if one is open.
+    /// Returns `true` if it was delivered immediately over the relay.
+    pub fn push(&self, device_id: &str, payload: Value) -> bool {
+        match self.channels.get(device_id) {
+            Some(tx) => tx.send(payload).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl Default for RelayRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,58 @@
+//! Per-request tracing span. [`RequestTracing`] opens one span per incoming
+//! request carrying a generated request id, method, and URI, and records the
+//! request's start and its response status inside that span. The span is
+//! not entered while the handler runs, so events a handler emits (Diesel
+//! errors, the `log_audit` result, `pool.get()` failures) do not nest under
+//! it on their own. Handlers that want their own events tagged with the
+//! same id take the [`RequestId`] guard.
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+pub struct RequestTracing;
+
+#[rocket::async_trait]
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _data: &mut Data<'_>) {
+        let request_id = Uuid::new_v4().to_string();
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %req.method(),
+            uri = %req.uri(),
+        );
+        span.in_scope(|| tracing::info!("request started"));
+
+        req.local_cache(|| RequestId(request_id.clone()));
+        req.local_cache(|| Some(span.clone()));
+    }
+
+    async fn on_response<'r>(&self, req: &Request<'r>, res: &mut Response<'r>) {
+        if let Some(span) = req.local_cache(|| None::<tracing::Span>) {
+            span.in_scope(|| tracing::info!(status = %res.status(), "request completed"));
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(
+            req.local_cache(|| RequestId(Uuid::new_v4().to_string()))
+                .clone(),
+        )
+    }
+}
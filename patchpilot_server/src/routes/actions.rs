@@ -1,155 +1,318 @@
-use diesel::prelude::*;
-use rocket::{get, post, serde::json::Json, State, request::{FromRequest, Outcome, Request}};
-use rocket::http::Status;
-use chrono::{Utc, Duration};
-
-use crate::auth::AuthUser;
-use crate::db::{DbPool, log_audit};
-use crate::models::{Action, NewAction, ActionTarget};
-use crate::schema::actions::{self, id as action_id_col, created_at, canceled};
-use crate::schema::action_targets::{self, action_id as at_action_id, device_id as at_device_id, status, last_update, response};
-
-/// AuthUser type implementing Rocket's FromRequest
-pub struct AuthUser {
-    pub username: String,
-}
-
-#[rocket::async_trait]
-impl<'r> rocket::request::FromRequest<'r> for AuthUser {
-    type Error = ();
-
-    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, (Status, ()), Status> {
-        let auth_header = request.headers().get_one("Authorization");
-
-        if let Some(token) = auth_header {
-            if token == "valid_token" {
-                return Outcome::Success(AuthUser { username: "admin".into() });
-            }
-        }
-
-        // Corrected: return a tuple (Status, ()) as required by Rocket
-        return Outcome::Failure((Status::Unauthorized, ()));
-    }
-}
-
-/// Submit a new action
-#[post("/api/actions", data = "<action>")]
-pub async fn submit_action(
-    pool: &State<DbPool>,
-    action: Json<NewAction>,
-    user: AuthUser,
-) -> Result<Status, Status> {
-    let username = user.username.clone();
-    let mut action_data = action.into_inner();
-    let pool = pool.inner().clone();
-
-    // Set default TTL 1 hour
-    let ttl_seconds = 3600;
-    action_data.expires_at = Utc::now().naive_utc() + Duration::seconds(ttl_seconds);
-
-    rocket::tokio::task::spawn_blocking(move || -> Result<Status, Status> {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-
-        diesel::insert_into(actions::table)
-            .values(&action_data)
-            .execute(&mut conn)
-            .map_err(|_| Status::InternalServerError)?;
-
-        log_audit(
-            &mut conn,
-            &username,
-            "submit_action",
-            Some(&action_data.id),
-            Some("Action submitted"),
-        )
-        .map_err(|_| Status::InternalServerError)?;
-
-        Ok(Status::Created)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)?
-}
-
-/// List all actions
-#[get("/api/actions")]
-pub async fn list_actions(pool: &State<DbPool>) -> Result<Json<Vec<Action>>, Status> {
-    let pool = pool.inner().clone();
-
-    let result: Vec<Action> = rocket::tokio::task::spawn_blocking(move || -> Result<_, Status> {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-        actions::table
-            .order(created_at.desc())
-            .load::<Action>(&mut conn)
-            .map_err(|_| Status::InternalServerError)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)??;
-
-    Ok(Json(result))
-}
-
-/// Cancel an action
-#[post("/api/actions/<action_id_param>")]
-pub async fn cancel_action(
-    pool: &State<DbPool>,
-    action_id_param: &str,
-    user: AuthUser,
-) -> Result<Status, Status> {
-    let username = user.username.clone();
-    let action_id_str = action_id_param.to_string();
-    let pool = pool.inner().clone();
-
-    rocket::tokio::task::spawn_blocking(move || -> Result<Status, Status> {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-
-        diesel::update(actions::table.filter(action_id_col.eq(&action_id_str)))
-            .set(canceled.eq(true))
-            .execute(&mut conn)
-            .map_err(|_| Status::InternalServerError)?;
-
-        log_audit(
-            &mut conn,
-            &username,
-            "cancel_action",
-            Some(&action_id_str),
-            Some("Action canceled"),
-        )
-        .map_err(|_| Status::InternalServerError)?;
-
-        Ok(Status::Ok)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)?
-}
-
-/// Report action target result
-#[post("/api/actions/<_ignored>/result", data = "<result>")]
-pub async fn report_action_result(
-    pool: &State<DbPool>,
-    _ignored: &str,
-    result: Json<ActionTarget>,
-) -> Result<Status, Status> {
-    let pool = pool.inner().clone();
-    let result = result.into_inner();
-
-    rocket::tokio::task::spawn_blocking(move || -> Result<Status, Status> {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-
-        diesel::update(
-            action_targets::table
-                .filter(at_action_id.eq(&result.action_id))
-                .filter(at_device_id.eq(&result.device_id)),
-        )
-        .set((
-            status.eq(&result.status),
-            last_update.eq(Utc::now().naive_utc()),
-            response.eq(&result.response),
-        ))
-        .execute(&mut conn)
-        .map_err(|_| Status::InternalServerError)?;
-
-        Ok(Status::Ok)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)?
-}
+use diesel::prelude::*;
+use rocket::{get, post, serde::json::Json, State};
+use rocket::http::Status;
+use chrono::{Utc, Duration};
+
+use crate::token_auth::TokenAuth;
+use crate::device_auth::DeviceAuth;
+use crate::db::{DbPool, log_audit};
+use crate::models::{Action, NewAction, NewHistoryRecord, ActionTarget, Device};
+use crate::schema::actions::{self, id as action_id_col, created_at, canceled};
+use crate::schema::action_targets::{self, action_id as at_action_id, device_id as at_device_id, status, last_update, response};
+use crate::schema::{devices, history_log};
+use crate::state::AppState;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Capability a device must have advertised in its heartbeat for a given
+/// `action_type` to be dispatched to it. Actions not listed here have no
+/// capability requirement and are sent to every target unconditionally.
+fn required_capability(action_type: &str) -> Option<&'static str> {
+    match action_type {
+        "pty" => Some("pty"),
+        "log_tail" => Some("log_tail"),
+        "self_update" => Some("self_update"),
+        _ => None,
+    }
+}
+
+/// Submit a new action
+#[post("/api/actions", data = "<action>")]
+pub async fn submit_action(
+    pool: &State<DbPool>,
+    app_state: &State<Arc<AppState>>,
+    action: Json<NewAction>,
+    user: TokenAuth,
+) -> Result<Status, Status> {
+    let username = user.username.clone();
+    let mut action_data = action.into_inner();
+    let pool = pool.inner().clone();
+
+    // Set default TTL 1 hour
+    let ttl_seconds = 3600;
+    action_data.expires_at = Utc::now().naive_utc() + Duration::seconds(ttl_seconds);
+
+    let action_data_clone = action_data.clone();
+    let required_cap = required_capability(&action_data.action_type);
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let targets: Vec<String> = conn
+        .interact(move |conn| -> Result<Vec<String>, Status> {
+            diesel::insert_into(actions::table)
+                .values(&action_data)
+                .execute(conn)
+                .map_err(|_| Status::InternalServerError)?;
+
+            log_audit(
+                conn,
+                &username,
+                "submit_action",
+                Some(&action_data.id),
+                Some("Action submitted"),
+            )
+            .map_err(|_| Status::InternalServerError)?;
+
+            let target_device_ids = action_targets::table
+                .filter(at_action_id.eq(&action_data.id))
+                .select(at_device_id)
+                .load::<String>(conn)
+                .unwrap_or_default();
+
+            // Skip relay push to targets that didn't advertise the capability
+            // this action needs, marking them as such rather than leaving them
+            // stuck in "pending" forever.
+            let dispatchable = if let Some(cap) = required_cap {
+                let mut dispatchable = Vec::with_capacity(target_device_ids.len());
+                for target in target_device_ids {
+                    let device_opt = devices::table
+                        .filter(devices::device_id.eq(&target))
+                        .first::<Device>(conn)
+                        .optional()
+                        .map_err(|_| Status::InternalServerError)?;
+
+                    // A self-update is platform-sensitive in a way a plain
+                    // capability flag doesn't capture (the agent binary
+                    // itself differs per OS) — refuse to send one to a
+                    // device whose platform we don't recognize rather than
+                    // dispatching it and letting the agent fail partway
+                    // through.
+                    let rejection = match &device_opt {
+                        Some(d) if !d.has_capability(cap) => Some("capability_missing"),
+                        Some(d) if action_data.action_type == "self_update"
+                            && !d.device_type().supports_auto_update() =>
+                        {
+                            Some("platform_unsupported")
+                        }
+                        Some(_) => None,
+                        None => Some("capability_missing"),
+                    };
+
+                    match rejection {
+                        None => dispatchable.push(target),
+                        Some(reason) => {
+                            diesel::update(
+                                action_targets::table
+                                    .filter(at_action_id.eq(&action_data.id))
+                                    .filter(at_device_id.eq(&target)),
+                            )
+                            .set((
+                                status.eq(reason),
+                                last_update.eq(Utc::now().naive_utc()),
+                            ))
+                            .execute(conn)
+                            .map_err(|_| Status::InternalServerError)?;
+                        }
+                    }
+                }
+                dispatchable
+            } else {
+                target_device_ids
+            };
+
+            Ok(dispatchable)
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)??;
+
+    // Fan the action out over each target's open relay connection
+    // immediately instead of waiting for its next heartbeat.
+    for device in &targets {
+        crate::routes::relay::push_action(app_state, device, &action_data_clone);
+    }
+
+    Ok(Status::Created)
+}
+
+/// List all actions
+#[get("/api/actions")]
+pub async fn list_actions(pool: &State<DbPool>) -> Result<Json<Vec<Action>>, Status> {
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let result: Vec<Action> = conn
+        .interact(|conn| -> Result<_, Status> {
+            actions::table
+                .order(created_at.desc())
+                .load::<Action>(conn)
+                .map_err(|_| Status::InternalServerError)
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)??;
+
+    Ok(Json(result))
+}
+
+/// Cancel an action
+#[post("/api/actions/<action_id_param>")]
+pub async fn cancel_action(
+    pool: &State<DbPool>,
+    action_id_param: &str,
+    user: TokenAuth,
+) -> Result<Status, Status> {
+    let username = user.username.clone();
+    let action_id_str = action_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        diesel::update(actions::table.filter(action_id_col.eq(&action_id_str)))
+            .set(canceled.eq(true))
+            .execute(conn)
+            .map_err(|_| Status::InternalServerError)?;
+
+        log_audit(
+            conn,
+            &username,
+            "cancel_action",
+            Some(&action_id_str),
+            Some("Action canceled"),
+        )
+        .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
+
+/// Report action target result. Reported by the device itself, so this
+/// takes a device access token rather than a user token — and the
+/// authenticated device must match the result it's reporting for, or a
+/// compromised agent could overwrite another device's action history.
+#[post("/api/actions/<_ignored>/result", data = "<result>")]
+pub async fn report_action_result(
+    pool: &State<DbPool>,
+    _ignored: &str,
+    result: Json<ActionTarget>,
+    auth: DeviceAuth,
+) -> Result<Status, Status> {
+    let result = result.into_inner();
+    if auth.device_id != result.device_id {
+        return Err(Status::Unauthorized);
+    }
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        diesel::update(
+            action_targets::table
+                .filter(at_action_id.eq(&result.action_id))
+                .filter(at_device_id.eq(&result.device_id)),
+        )
+        .set((
+            status.eq(&result.status),
+            last_update.eq(Utc::now().naive_utc()),
+            response.eq(&result.response),
+        ))
+        .execute(conn)
+        .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
+
+/// Agent polls this to learn whether the command it's about to run (or is
+/// still running) has been canceled or has expired, so it can skip/abort
+/// instead of running to completion and reporting into the void. Status is
+/// derived straight from the `actions` table's existing `canceled`/
+/// `expires_at` columns, same as `watch::watch_status`.
+#[get("/devices/<_device_id_param>/commands/<cmd_id_param>/status")]
+pub async fn command_status(
+    pool: &State<DbPool>,
+    _device_id_param: &str,
+    cmd_id_param: &str,
+) -> Result<Json<serde_json::Value>, Status> {
+    let cmd_id_str = cmd_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let (canceled_val, expired) = conn
+        .interact(move |conn| -> Result<(bool, bool), Status> {
+            let action = actions::table
+                .filter(action_id_col.eq(&cmd_id_str))
+                .first::<Action>(conn)
+                .optional()
+                .map_err(|_| Status::InternalServerError)?;
+
+            Ok(match action {
+                Some(a) => (a.canceled, a.expires_at <= Utc::now().naive_utc()),
+                // An unknown command id can't be vouched for either way —
+                // treat it as neither canceled nor expired so a command
+                // delivered out-of-band (e.g. a relay push not yet
+                // persisted) isn't aborted by a false positive.
+                None => (false, false),
+            })
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)??;
+
+    Ok(Json(serde_json::json!({
+        "canceled": canceled_val,
+        "expired": expired,
+    })))
+}
+
+/// One execution or post failure the agent couldn't get through to the
+/// server the first time, reported for the audit trail once the agent's
+/// own retry loop gets through.
+#[derive(Debug, Deserialize)]
+pub struct CommandErrorReport {
+    pub phase: String,
+    pub message: String,
+}
+
+/// Agent-reported execution/post failure for a command, recorded into the
+/// same `audit`/`history_log` tables every other action lifecycle event
+/// feeds, so a dropped result doesn't also mean a silent gap in the record.
+#[post("/devices/<device_id_param>/commands/<cmd_id_param>/errors", data = "<report>")]
+pub async fn report_command_error(
+    pool: &State<DbPool>,
+    device_id_param: &str,
+    cmd_id_param: &str,
+    report: Json<CommandErrorReport>,
+    auth: DeviceAuth,
+) -> Result<Status, Status> {
+    if auth.device_id != device_id_param {
+        return Err(Status::Unauthorized);
+    }
+    let report = report.into_inner();
+    let cmd_id_str = cmd_id_param.to_string();
+    let device_id_str = device_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        log_audit(
+            conn,
+            &device_id_str,
+            &format!("command_{}", report.phase),
+            Some(&cmd_id_str),
+            Some(&report.message),
+        )
+        .map_err(|_| Status::InternalServerError)?;
+
+        let history = NewHistoryRecord::new(
+            Some(cmd_id_str.clone()),
+            None,
+            Some(device_id_str.clone()),
+            format!("command_{}", report.phase),
+            Some(report.message.clone()),
+        );
+
+        diesel::insert_into(history_log::table)
+            .values(&history)
+            .execute(conn)
+            .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
@@ -0,0 +1,27 @@
+//! Read-only endpoint for checking the `audit` table's hash chain (see
+//! `db::verify_audit_chain`) without needing DB access directly.
+use rocket::{get, http::Status, serde::json::Json, State};
+
+use crate::auth::{AuthUser, UserRole};
+use crate::db::{self, DbPool};
+
+/// Recompute the audit log's hash chain and report whether it's intact.
+#[get("/audit/verify")]
+pub async fn verify_audit(user: AuthUser, pool: &State<DbPool>) -> Result<Json<serde_json::Value>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let broken_at_id = conn
+        .interact(db::verify_audit_chain)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(serde_json::json!({
+        "intact": broken_at_id.is_none(),
+        "broken_at_id": broken_at_id,
+    })))
+}
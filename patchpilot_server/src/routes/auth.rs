@@ -1,6 +1,7 @@
 use rocket::form::Form;
-use rocket::http::CookieJar;
+use rocket::http::{CookieJar, Status};
 use rocket::response::{Redirect, content::RawHtml};
+use rocket::serde::json::Json;
 use rocket::State;
 
 use diesel::prelude::*;
@@ -9,6 +10,7 @@ use diesel::SelectableHelper;
 use std::fs::read_to_string;
 
 use crate::db::DbPool;
+use crate::opaque_auth::OpaqueLoginSessions;
 use crate::schema::users;
 
 #[derive(FromForm)]
@@ -24,52 +26,210 @@ struct UserRow {
     pub id: i32,
     pub username: String,
     pub password_hash: String,
+    pub opaque_password_file: Option<Vec<u8>>,
 }
 
+/// Legacy argon2 login, kept only for users who haven't been re-enrolled
+/// into OPAQUE yet (see `opaque_auth::enroll_from_plaintext`). A successful
+/// check here is also the one moment the server ever legitimately holds a
+/// plaintext password, so it's used to enroll the user into OPAQUE on the
+/// spot — their next login goes through `/login/start`+`/login/finish`
+/// instead.
 #[post("/login", data = "<form>")]
-pub fn login(
+pub async fn login(
     form: Form<LoginForm>,
     cookies: &CookieJar<'_>,
     pool: &State<DbPool>,
+    opaque_setup: &State<std::sync::Arc<crate::opaque_auth::PatchPilotServerSetup>>,
 ) -> Redirect {
-    use crate::schema::users::dsl::*;
-
-    let mut conn = match pool.get() {
+    let conn = match pool.get().await {
         Ok(c) => c,
         Err(_) => return Redirect::to("/login"),
     };
 
-    let user_opt = users
-        .filter(username.eq(&form.username))
-        .select(UserRow::as_select())
-        .first::<UserRow>(&mut conn)
-        .optional()
+    let username_input = form.username.clone();
+    let password_input = form.password.clone();
+    let setup = opaque_setup.inner().clone();
+
+    let logged_in_as = conn
+        .interact(move |conn| {
+            use crate::schema::users::dsl::*;
+
+            let user_opt = users
+                .filter(username.eq(&username_input))
+                .select(UserRow::as_select())
+                .first::<UserRow>(conn)
+                .optional()
+                .unwrap_or(None);
+
+            user_opt.and_then(|user| {
+                if crate::auth::verify_password(&password_input, &user.password_hash) {
+                    let _ = crate::db::log_audit(
+                        conn,
+                        &user.username,
+                        "login",
+                        None,
+                        Some("User logged in"),
+                    );
+
+                    if user.opaque_password_file.is_none() {
+                        if let Ok(password_file) =
+                            crate::opaque_auth::enroll_from_plaintext(&setup, &user.username, &password_input)
+                        {
+                            let _ = diesel::update(users.filter(id.eq(user.id)))
+                                .set(opaque_password_file.eq(Some(password_file)))
+                                .execute(conn);
+                        }
+                    }
+
+                    Some(user)
+                } else {
+                    None
+                }
+            })
+        })
+        .await
         .unwrap_or(None);
 
-    if let Some(user) = user_opt {
-        let actual_username = user.username.clone(); // now actually read
-
-        if bcrypt::verify(&form.password, &user.password_hash).unwrap_or(false) {
-            cookies.add_private(
-                rocket::http::Cookie::new("user_id", user.id.to_string())
-            );
-
-            // Optionally log successful login in audit
-            let _ = crate::routes::history::log_audit(
-                &mut conn,
-                &actual_username,
-                "login",
-                None,
-                Some("User logged in"),
-            );
-
-            return Redirect::to("/dashboard");
-        }
+    if let Some(user) = logged_in_as {
+        cookies.add_private(
+            rocket::http::Cookie::new("user_id", user.id.to_string())
+        );
+        return Redirect::to("/dashboard");
     }
 
     Redirect::to("/login")
 }
 
+#[derive(serde::Deserialize)]
+pub struct LoginStartRequest {
+    pub username: String,
+    /// Base64 serialized OPAQUE `CredentialRequest`.
+    pub credential_request: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct LoginStartResponse {
+    pub session_id: String,
+    /// Base64 serialized OPAQUE `CredentialResponse`.
+    pub credential_response: String,
+}
+
+/// Round 1 of the OPAQUE login: the client sends its blinded credential
+/// request, the server derives its credential response from the user's
+/// stored password file (or a consistent-looking dummy if the username
+/// doesn't exist or hasn't been enrolled yet, via `ServerLogin::start`'s
+/// own handling of `password_file: None`) and stashes the resulting state
+/// under a session id for `/login/finish` to pick back up.
+#[post("/login/start", data = "<body>")]
+pub async fn login_start(
+    body: Json<LoginStartRequest>,
+    pool: &State<DbPool>,
+    opaque_setup: &State<std::sync::Arc<crate::opaque_auth::PatchPilotServerSetup>>,
+    sessions: &State<std::sync::Arc<OpaqueLoginSessions>>,
+) -> Result<Json<LoginStartResponse>, Status> {
+    use base64::Engine;
+
+    let body = body.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let setup = opaque_setup.inner().clone();
+
+    let username_input = body.username.clone();
+    let password_file = conn
+        .interact(move |conn| {
+            use crate::schema::users::dsl::*;
+            users
+                .filter(username.eq(&username_input))
+                .select(opaque_password_file)
+                .first::<Option<Vec<u8>>>(conn)
+                .optional()
+                .unwrap_or(None)
+                .flatten()
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let credential_request_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&body.credential_request)
+        .map_err(|_| Status::BadRequest)?;
+
+    let (state, credential_response_bytes) = crate::opaque_auth::login_start(
+        &setup,
+        password_file,
+        &body.username,
+        &credential_request_bytes,
+    )
+    .map_err(|_| Status::BadRequest)?;
+
+    let session_id = sessions.insert(state, &body.username);
+
+    Ok(Json(LoginStartResponse {
+        session_id,
+        credential_response: base64::engine::general_purpose::STANDARD.encode(credential_response_bytes),
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginFinishRequest {
+    pub session_id: String,
+    /// Base64 serialized OPAQUE `CredentialFinalization`.
+    pub credential_finalization: String,
+}
+
+/// Round 2 of the OPAQUE login: the client proves it derived the same
+/// shared secret the server did, without either side ever having sent the
+/// password itself. Success sets the same private `user_id` cookie the
+/// legacy form login does.
+#[post("/login/finish", data = "<body>")]
+pub async fn login_finish(
+    body: Json<LoginFinishRequest>,
+    cookies: &CookieJar<'_>,
+    pool: &State<DbPool>,
+    sessions: &State<std::sync::Arc<OpaqueLoginSessions>>,
+) -> Result<Status, Status> {
+    use base64::Engine;
+
+    let body = body.into_inner();
+    let (state, username_input) = sessions.take(&body.session_id).ok_or(Status::Unauthorized)?;
+
+    let finalization_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&body.credential_finalization)
+        .map_err(|_| Status::BadRequest)?;
+
+    crate::opaque_auth::login_finish(state, &finalization_bytes).map_err(|_| Status::Unauthorized)?;
+
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let user_id = conn
+        .interact(move |conn| {
+            use crate::schema::users::dsl::*;
+            let found = users
+                .filter(username.eq(&username_input))
+                .select(id)
+                .first::<i32>(conn)
+                .optional()
+                .unwrap_or(None);
+
+            if found.is_some() {
+                let _ = crate::db::log_audit(
+                    conn,
+                    &username_input,
+                    "login",
+                    None,
+                    Some("User logged in via OPAQUE"),
+                );
+            }
+
+            found
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::Unauthorized)?;
+
+    cookies.add_private(rocket::http::Cookie::new("user_id", user_id.to_string()));
+
+    Ok(Status::Ok)
+}
+
 #[get("/logout")]
 pub fn logout(cookies: &CookieJar<'_>) -> Redirect {
     cookies.remove_private(rocket::http::Cookie::build("user_id").build());
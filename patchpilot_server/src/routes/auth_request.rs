@@ -0,0 +1,204 @@
+//! Device-approval handshake ("login with device"): rather than trusting a
+//! raw heartbeat to adopt a new agent, the agent proves it holds the
+//! private half of a keypair it generates for the occasion. The operator
+//! approves from the dashboard after comparing the access code shown in
+//! the agent's own console/logs against the one submitted here; on
+//! approval the server seals a fresh adoption secret to the agent's public
+//! key, so only the agent that generated the keypair can ever read it.
+use diesel::prelude::*;
+use rand::rngs::OsRng;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::{get, post, State};
+
+use crate::auth::{AuthUser, UserRole};
+use crate::db::{self, DbPool};
+use crate::models::AuthRequest as AuthRequestRow;
+
+/// The caller's IP as Rocket sees it (see `Request::client_ip`), recorded
+/// alongside the request purely as an audit breadcrumb for the operator —
+/// nothing in the handshake's security depends on it.
+struct ClientIp(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ClientIp(req.client_ip().map(|ip| ip.to_string())))
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SubmitAuthRequest {
+    pub device_id: String,
+    /// Base64-encoded X25519 public key the agent generated for this
+    /// handshake.
+    pub public_key: String,
+    /// Short random code the agent also prints to its own console/logs, so
+    /// the operator can visually confirm they're approving the device in
+    /// front of them and not some other pending request.
+    pub access_code: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubmitAuthRequestResponse {
+    pub request_id: String,
+}
+
+/// Agent kicks off the handshake with its ephemeral public key and access
+/// code.
+#[post("/auth-request", data = "<body>")]
+pub async fn submit_auth_request(
+    body: Json<SubmitAuthRequest>,
+    client_ip: ClientIp,
+    pool: &State<DbPool>,
+) -> Result<Json<SubmitAuthRequestResponse>, Status> {
+    let body = body.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let request_id = conn
+        .interact(move |conn| {
+            db::create_auth_request(
+                conn,
+                &body.device_id,
+                client_ip.0.as_deref(),
+                &body.public_key,
+                &body.access_code,
+            )
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(SubmitAuthRequestResponse { request_id }))
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AuthRequestStatus {
+    Pending,
+    Rejected,
+    Approved { secret_enc: String },
+}
+
+/// Agent polls this until the operator has made a decision. Still a plain
+/// request/response poll like the rest of this server's "long-poll" style
+/// endpoints (see `routes::shell`) — the agent is expected to retry on an
+/// interval, not block a connection open.
+#[get("/auth-request/<request_id>")]
+pub async fn poll_auth_request(
+    request_id: &str,
+    pool: &State<DbPool>,
+) -> Result<Json<AuthRequestStatus>, Status> {
+    let request_id = request_id.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let row = conn
+        .interact(move |conn| db::find_auth_request(conn, &request_id))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    Ok(Json(match row.approved {
+        None => AuthRequestStatus::Pending,
+        Some(false) => AuthRequestStatus::Rejected,
+        Some(true) => AuthRequestStatus::Approved {
+            secret_enc: row.encrypted_secret.unwrap_or_default(),
+        },
+    }))
+}
+
+/// List every undecided auth request, for the dashboard's approval queue.
+#[get("/auth-request")]
+pub async fn list_auth_requests(
+    user: AuthUser,
+    pool: &State<DbPool>,
+) -> Result<Json<Vec<AuthRequestRow>>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let rows = conn
+        .interact(db::list_pending_auth_requests)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(rows))
+}
+
+/// Generate a fresh adoption secret and seal it to `public_key_b64` so only
+/// the holder of the matching private key can read it back.
+fn seal_adoption_secret(public_key_b64: &str) -> Result<String, Status> {
+    use base64::Engine;
+    use crypto_box::PublicKey;
+
+    let key_bytes = base64::engine::general_purpose::STANDARD
+        .decode(public_key_b64)
+        .map_err(|_| Status::BadRequest)?;
+    let key_bytes: [u8; 32] = key_bytes.try_into().map_err(|_| Status::BadRequest)?;
+    let device_public_key = PublicKey::from(key_bytes);
+
+    let mut secret = [0u8; 32];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut secret);
+
+    let sealed = crypto_box::seal(&mut OsRng, &device_public_key, &secret)
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(sealed))
+}
+
+/// Operator approves or rejects a pending request from the dashboard.
+#[post("/auth-request/<request_id>/decide?<approve>")]
+pub async fn decide_auth_request(
+    user: AuthUser,
+    request_id: &str,
+    approve: bool,
+    pool: &State<DbPool>,
+) -> Result<Status, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let request_id_owned = request_id.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let row = conn
+        .interact({
+            let request_id = request_id_owned.clone();
+            move |conn| db::find_auth_request(conn, &request_id)
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    let encrypted_secret = if approve {
+        Some(seal_adoption_secret(&row.public_key)?)
+    } else {
+        None
+    };
+
+    let username = user.username.clone();
+    conn.interact(move |conn| -> Result<(), diesel::result::Error> {
+        db::decide_auth_request(conn, &request_id_owned, approve, encrypted_secret.as_deref())?;
+        db::log_audit(
+            conn,
+            &username,
+            if approve { "approve_auth_request" } else { "reject_auth_request" },
+            Some(&row.device_id),
+            None,
+        )?;
+        Ok(())
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Status::Ok)
+}
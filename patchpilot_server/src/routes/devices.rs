@@ -1,116 +1,491 @@
-use rocket::serde::json::Json;
-use rocket::{State, http::Status, get, post};
-use diesel::prelude::*;
-use chrono::Utc;
-
-use crate::db::DbPool;
-use crate::auth::{AuthUser, UserRole};
-use crate::models::{Device, DeviceInfo, NewDevice};
-use crate::schema::devices::dsl::*;
-use crate::schema::server_settings::dsl as settings_dsl;
-use crate::db::log_audit;
-
-/// Get all devices
-#[get("/devices")]
-pub async fn get_devices(pool: &State<DbPool>) -> Result<Json<Vec<Device>>, Status> {
-    let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-    let result = devices
-        .load::<Device>(&mut conn)
-        .map_err(|_| Status::InternalServerError)?;
-    Ok(Json(result))
-}
-
-/// Get details for a specific device
-#[get("/device/<device_id_param>")]
-pub async fn get_device_details(
-    pool: &State<DbPool>,
-    device_id_param: &str
-) -> Result<Json<Device>, Status> {
-    let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-    let device = devices
-        .filter(device_id.eq(device_id_param))
-        .first::<Device>(&mut conn)
-        .map_err(|_| Status::NotFound)?;
-    Ok(Json(device))
-}
-
-/// Approve a device
-#[post("/approve/<device_id_param>")]
-pub async fn approve_device(
-    pool: &State<DbPool>,
-    device_id_param: &str,
-    user: AuthUser,
-) -> Result<Status, Status> {
-    if !user.has_role(&UserRole::Admin) {
-        return Err(Status::Unauthorized);
-    }
-
-    let username = user.username.clone();
-    let device_id_str = device_id_param.to_string();
-    let pool = pool.inner().clone();
-
-    rocket::tokio::task::spawn_blocking(move || {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-        diesel::update(devices.filter(device_id.eq(&device_id_str)))
-            .set(approved.eq(true))
-            .execute(&mut conn)
-            .map_err(|_| Status::InternalServerError)?;
-
-        log_audit(&mut conn, &username, "approve_device", Some(&device_id_str), Some("Device approved"))
-            .map_err(|_| Status::InternalServerError)?;
-
-        Ok(Status::Ok)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)?
-}
-
-/// Register or update a device
-#[post("/register_or_update", data = "<info>")]
-pub async fn register_or_update_device(
-    pool: &State<DbPool>,
-    info: Json<DeviceInfo>,
-    user: AuthUser,
-) -> Result<Json<serde_json::Value>, Status> {
-    if !user.has_role(&UserRole::Admin) {
-        return Err(Status::Unauthorized);
-    }
-
-    let username = user.username.clone();
-    let info = info.into_inner();
-    let pool = pool.inner().clone();
-
-    let result = rocket::tokio::task::spawn_blocking(move || -> Result<serde_json::Value, Status> {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-
-        // Load existing device if it exists
-        let existing = devices
-            .filter(device_id.eq(&info.device_id))
-            .first::<Device>(&mut conn)
-            .optional()
-            .map_err(|_| Status::InternalServerError)?;
-
-        // Ensure last_checkin timestamp is updated
-        let now = Utc::now();
-        let mut updated = NewDevice::from_device_info(&info.device_id, &info, existing.as_ref());
-        updated.last_checkin = now.naive_utc();
-
-        // Insert or update device
-        diesel::insert_into(devices)
-            .values(&updated)
-            .on_conflict(device_id)
-            .do_update()
-            .set(&updated)
-            .execute(&mut conn)
-            .map_err(|_| Status::InternalServerError)?;
-
-        // Log audit
-        log_audit(&mut conn, &username, "register_or_update_device", Some(&info.device_id), Some("Device registered or updated"))
-            .map_err(|_| Status::InternalServerError)?;
-
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)??;
-
-    Ok(Json(result))
-}
+use rocket::serde::json::Json;
+use rocket::{State, http::Status, get, post};
+use diesel::prelude::*;
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::db::DbPool;
+use crate::auth::{AuthUser, UserRole};
+use crate::state::AppState;
+use crate::token_auth::TokenAuth;
+use crate::models::{Device, DeviceInfo, LatencySample, NewDevice, SystemInfo};
+use crate::schema::devices::dsl::*;
+use crate::schema::server_settings::dsl as settings_dsl;
+use crate::db::log_audit;
+
+/// Oldest protocol version this server will still adopt actions to.
+/// Agents below this are recorded as outdated rather than silently handed
+/// work they can't run.
+pub const MIN_PROTOCOL_VERSION: i32 = 1;
+
+/// Protocol version this server speaks, returned in every heartbeat
+/// response so agents can detect drift.
+pub const SERVER_PROTOCOL_VERSION: i32 = 2;
+
+/// Capabilities this server knows how to use. An agent's advertised
+/// capabilities are intersected against this list before being stored, so
+/// a newer agent talking to an older server doesn't get credited with
+/// capabilities the server can't actually dispatch to.
+pub const KNOWN_CAPABILITIES: &[&str] = &["pty", "self_update", "relay", "log_tail", "stream"];
+
+/// Cap on how many `latency_history` rows a single request returns.
+const LATENCY_HISTORY_LIMIT: i64 = 200;
+
+#[derive(serde::Deserialize)]
+pub struct HeartbeatPayload {
+    pub device_id: String,
+    /// Kept as a raw `Value` (rather than the typed `SystemInfo`) so its
+    /// exact on-the-wire bytes can be rehashed and checked against
+    /// `signature` before it's parsed into anything — see
+    /// `verify_heartbeat_signature`.
+    #[serde(default)]
+    pub system_info: serde_json::Value,
+    pub device_type: Option<String>,
+    pub device_model: Option<String>,
+    #[serde(default)]
+    pub protocol_version: Option<i32>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Single-use value from `GET /nonce`, bound into the signed message so
+    /// a captured heartbeat can't be replayed.
+    pub nonce: String,
+    /// Base64 detached Ed25519 signature over
+    /// `nonce || device_id || sha256(system_info)`.
+    pub signature: String,
+    /// Base64 Ed25519 public key, present only on the device's first-ever
+    /// heartbeat. Trusted on first use and pinned to the device row; every
+    /// heartbeat after that must verify against the pinned key instead.
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+/// Verify `payload`'s signature against `pinned_key` (the device's stored
+/// `public_key`, if it has one yet). Returns the decoded `SystemInfo` on
+/// success so the caller never touches the system-info update before the
+/// signature covering it has checked out.
+fn verify_heartbeat_signature(
+    payload: &HeartbeatPayload,
+    pinned_key: Option<&str>,
+) -> Result<SystemInfo, Status> {
+    use base64::Engine;
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+    use sha2::{Digest, Sha256};
+
+    let key_b64 = pinned_key
+        .or(payload.public_key.as_deref())
+        .ok_or(Status::Unauthorized)?;
+
+    let key_bytes = base64::engine::general_purpose::STANDARD
+        .decode(key_b64)
+        .map_err(|_| Status::Unauthorized)?;
+    let key_bytes: [u8; 32] = key_bytes.try_into().map_err(|_| Status::Unauthorized)?;
+    let verifying_key = VerifyingKey::from_bytes(&key_bytes).map_err(|_| Status::Unauthorized)?;
+
+    let sig_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&payload.signature)
+        .map_err(|_| Status::Unauthorized)?;
+    let sig_bytes: [u8; 64] = sig_bytes.try_into().map_err(|_| Status::Unauthorized)?;
+    let signature = Signature::from_bytes(&sig_bytes);
+
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&payload.system_info).unwrap_or_default());
+    let system_info_hash = hasher.finalize();
+
+    let mut message = Vec::new();
+    message.extend_from_slice(payload.nonce.as_bytes());
+    message.extend_from_slice(payload.device_id.as_bytes());
+    message.extend_from_slice(&system_info_hash);
+
+    verifying_key
+        .verify(&message, &signature)
+        .map_err(|_| Status::Unauthorized)?;
+
+    serde_json::from_value(payload.system_info.clone()).map_err(|_| Status::BadRequest)
+}
+
+/// Issue a single-use nonce for the next heartbeat's replay-protection
+/// signature.
+#[get("/nonce")]
+pub async fn issue_nonce(pool: &State<DbPool>) -> Result<Json<serde_json::Value>, Status> {
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let nonce_val = conn
+        .interact(crate::db::issue_nonce)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(serde_json::json!({ "nonce": nonce_val })))
+}
+
+/// Heartbeat: adoption polling, protocol/capability negotiation, and
+/// liveness in one request. The agent's `protocol_version` is checked
+/// against [`MIN_PROTOCOL_VERSION`]; agents below it are flagged via
+/// `devices.protocol_outdated` rather than silently issued actions they
+/// can't run (the dispatcher checks `Device::has_capability` before
+/// targeting one — see `actions::submit_action`). Every heartbeat must also
+/// carry a detached Ed25519 signature over a fresh server-issued nonce (see
+/// `verify_heartbeat_signature`); a bare `device_id` is no longer enough to
+/// claim to be a given device.
+///
+/// Once a device has been approved and issued a refresh token (see
+/// `exchange_device_token`), it must also present a valid device access
+/// token as `device_auth` on every subsequent heartbeat — a brand-new,
+/// not-yet-approved device has no token yet, so that requirement only
+/// kicks in once one has actually been issued.
+#[post("/devices/heartbeat", data = "<payload>")]
+pub async fn heartbeat(
+    pool: &State<DbPool>,
+    payload: Json<HeartbeatPayload>,
+    device_auth: Option<crate::device_auth::DeviceAuth>,
+    app_state: &State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let payload = payload.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let snapshot_device_id = payload.device_id.clone();
+    let snapshot_system_info = payload.system_info.clone();
+
+    let response = conn.interact(move |conn| -> Result<Json<serde_json::Value>, Status> {
+        let nonce_ok = crate::db::consume_nonce(conn, &payload.nonce)
+            .map_err(|_| Status::InternalServerError)?;
+        if !nonce_ok {
+            return Err(Status::Unauthorized);
+        }
+
+        let negotiated_version = payload.protocol_version.unwrap_or(0);
+        let outdated = negotiated_version < MIN_PROTOCOL_VERSION;
+        let negotiated_caps: Vec<&str> = KNOWN_CAPABILITIES
+            .iter()
+            .copied()
+            .filter(|cap| payload.capabilities.iter().any(|c| c == cap))
+            .collect();
+        let caps_str = negotiated_caps.join(",");
+
+        let existing = devices
+            .filter(device_id.eq(&payload.device_id))
+            .first::<Device>(conn)
+            .optional()
+            .map_err(|_| Status::InternalServerError)?;
+
+        let system_info_val =
+            verify_heartbeat_signature(&payload, existing.as_ref().and_then(|d| d.public_key.as_deref()))?;
+
+        // Keep a history of every measured round-trip, independent of
+        // whether this heartbeat ends up adopting/updating the device row,
+        // so `latency_history` can chart a trend rather than just the
+        // latest reading.
+        crate::db::record_latency_sample(conn, &payload.device_id, system_info_val.server_latency_ms)
+            .map_err(|_| Status::InternalServerError)?;
+
+        if let Some(d) = &existing {
+            if d.refresh_token_hash.is_some() {
+                device_auth
+                    .as_ref()
+                    .filter(|a| a.device_id == payload.device_id)
+                    .ok_or(Status::Unauthorized)?;
+            }
+        }
+
+        let had_refresh_token = existing.as_ref().is_some_and(|d| d.refresh_token_hash.is_some());
+
+        let is_adopted = match existing {
+            Some(d) => {
+                let probe_results_json = serde_json::to_string(&system_info_val.probe_results).ok();
+                diesel::update(devices.filter(device_id.eq(&payload.device_id)))
+                    .set((
+                        last_checkin.eq(Utc::now().naive_utc()),
+                        protocol_version.eq(Some(negotiated_version)),
+                        capabilities.eq(Some(&caps_str)),
+                        protocol_outdated.eq(outdated),
+                        ping_latency.eq(system_info_val.server_latency_ms),
+                        probe_results.eq(probe_results_json),
+                    ))
+                    .execute(conn)
+                    .map_err(|_| Status::InternalServerError)?;
+
+                if d.public_key.is_none() {
+                    if let Some(key) = &payload.public_key {
+                        diesel::update(devices.filter(device_id.eq(&payload.device_id)))
+                            .set(public_key.eq(Some(key.as_str())))
+                            .execute(conn)
+                            .map_err(|_| Status::InternalServerError)?;
+                    }
+                }
+
+                d.approved
+            }
+            None => {
+                // Heartbeats before the device has completed `/api/register_or_update`
+                // shouldn't happen in practice, but don't 500 on a race.
+                let info = DeviceInfo {
+                    device_id: payload.device_id.clone(),
+                    system_info: system_info_val,
+                    device_type: payload.device_type.clone(),
+                    device_model: payload.device_model.clone(),
+                };
+                let new_device = NewDevice::from_device_info(&payload.device_id, &info, None);
+                diesel::insert_into(devices)
+                    .values(&new_device)
+                    .execute(conn)
+                    .map_err(|_| Status::InternalServerError)?;
+                diesel::update(devices.filter(device_id.eq(&payload.device_id)))
+                    .set((
+                        protocol_version.eq(Some(negotiated_version)),
+                        capabilities.eq(Some(&caps_str)),
+                        protocol_outdated.eq(outdated),
+                        public_key.eq(payload.public_key.as_deref()),
+                    ))
+                    .execute(conn)
+                    .map_err(|_| Status::InternalServerError)?;
+                false
+            }
+        };
+
+        // The first heartbeat to see `approved == true` mints this device's
+        // refresh token and hands it back once; every heartbeat after that
+        // must present an access token exchanged from it (see the guard
+        // check above).
+        let issued_refresh_token = if is_adopted && !had_refresh_token {
+            let raw_token = crate::device_auth::generate_device_refresh_token();
+            let hash = crate::device_auth::hash_device_refresh_token(&raw_token);
+            crate::db::set_device_refresh_token_hash(conn, &payload.device_id, Some(&hash))
+                .map_err(|_| Status::InternalServerError)?;
+            Some(raw_token)
+        } else {
+            None
+        };
+
+        Ok(Json(serde_json::json!({
+            "adopted": is_adopted,
+            "protocol_version": SERVER_PROTOCOL_VERSION,
+            "capabilities": negotiated_caps,
+            "protocol_outdated": outdated,
+            "refresh_token": issued_refresh_token,
+        })))
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)??;
+
+    // Best-effort: a snapshot write failing shouldn't fail the heartbeat
+    // the device is actually waiting on.
+    if let Err(e) = crate::storage::store_system_info_snapshot(
+        &app_state.storage,
+        &snapshot_device_id,
+        &snapshot_system_info,
+    )
+    .await
+    {
+        tracing::warn!("Failed to persist system-info snapshot for {}: {}", snapshot_device_id, e);
+    }
+
+    Ok(response)
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeviceTokenRequest {
+    pub device_id: String,
+    pub refresh_token: String,
+}
+
+/// Exchange a device's refresh token for a short-lived access token,
+/// rotating the refresh token in the same call: the old one stops working
+/// the instant a new one is issued, so a captured-but-unused refresh token
+/// has a single-use window rather than standing access forever.
+#[post("/token", data = "<body>")]
+pub async fn exchange_device_token(
+    pool: &State<DbPool>,
+    app_state: &State<Arc<AppState>>,
+    body: Json<DeviceTokenRequest>,
+) -> Result<Json<serde_json::Value>, Status> {
+    let body = body.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let app_state = app_state.inner().clone();
+
+    conn.interact(move |conn| -> Result<Json<serde_json::Value>, Status> {
+        let presented_hash = crate::device_auth::hash_device_refresh_token(&body.refresh_token);
+
+        let matched = crate::db::find_device_by_refresh_token_hash(conn, &presented_hash)
+            .map_err(|_| Status::InternalServerError)?
+            .filter(|d| d.device_id == body.device_id)
+            .ok_or(Status::Unauthorized)?;
+
+        let access_token = crate::device_auth::issue_device_access_token(&matched.device_id, &app_state)
+            .map_err(|_| Status::InternalServerError)?;
+
+        let new_refresh_token = crate::device_auth::generate_device_refresh_token();
+        let new_hash = crate::device_auth::hash_device_refresh_token(&new_refresh_token);
+        crate::db::set_device_refresh_token_hash(conn, &matched.device_id, Some(&new_hash))
+            .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Json(serde_json::json!({
+            "access_token": access_token,
+            "refresh_token": new_refresh_token,
+        })))
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
+
+/// Revoke a device's refresh token, cutting off a lost or decommissioned
+/// agent immediately — it can no longer exchange for a fresh access token,
+/// without deleting the device's history or un-approving it.
+#[post("/revoke_device_token/<device_id_param>")]
+pub async fn revoke_device_token(
+    pool: &State<DbPool>,
+    device_id_param: &str,
+    user: AuthUser,
+) -> Result<Status, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let username = user.username.clone();
+    let device_id_str = device_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        crate::db::set_device_refresh_token_hash(conn, &device_id_str, None)
+            .map_err(|_| Status::InternalServerError)?;
+
+        log_audit(conn, &username, "revoke_device_token", Some(&device_id_str), Some("Device refresh token revoked"))
+            .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
+
+/// Recent server-latency samples for a device, newest first, so the
+/// dashboard can chart a trend and flag an agent whose path to the server
+/// is degrading before it actually drops off — a single slow heartbeat
+/// looks very different from several in a row getting worse.
+#[get("/device/<device_id_param>/latency_history")]
+pub async fn latency_history(
+    pool: &State<DbPool>,
+    device_id_param: &str,
+) -> Result<Json<Vec<LatencySample>>, Status> {
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let device_id_str = device_id_param.to_string();
+
+    let result = conn
+        .interact(move |conn| {
+            use crate::schema::latency_history::dsl::*;
+            latency_history
+                .filter(device_id.eq(&device_id_str))
+                .order(recorded_at.desc())
+                .limit(LATENCY_HISTORY_LIMIT)
+                .load::<LatencySample>(conn)
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(result))
+}
+
+/// Get all devices
+#[get("/devices")]
+pub async fn get_devices(pool: &State<DbPool>) -> Result<Json<Vec<Device>>, Status> {
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let result = conn
+        .interact(|conn| devices.load::<Device>(conn))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(result))
+}
+
+/// Get details for a specific device
+#[get("/device/<device_id_param>")]
+pub async fn get_device_details(
+    pool: &State<DbPool>,
+    device_id_param: &str
+) -> Result<Json<Device>, Status> {
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    let device_id_str = device_id_param.to_string();
+    let device = conn
+        .interact(move |conn| devices.filter(device_id.eq(&device_id_str)).first::<Device>(conn))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::NotFound)?;
+    Ok(Json(device))
+}
+
+/// Approve a device
+#[post("/approve/<device_id_param>")]
+pub async fn approve_device(
+    pool: &State<DbPool>,
+    device_id_param: &str,
+    user: AuthUser,
+) -> Result<Status, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let username = user.username.clone();
+    let device_id_str = device_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        diesel::update(devices.filter(device_id.eq(&device_id_str)))
+            .set(approved.eq(true))
+            .execute(conn)
+            .map_err(|_| Status::InternalServerError)?;
+
+        log_audit(conn, &username, "approve_device", Some(&device_id_str), Some("Device approved"))
+            .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
+
+/// Register or update a device
+#[post("/register_or_update", data = "<info>")]
+pub async fn register_or_update_device(
+    pool: &State<DbPool>,
+    info: Json<DeviceInfo>,
+    user: TokenAuth,
+) -> Result<Json<serde_json::Value>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let username = user.username.clone();
+    let info = info.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let result = conn
+        .interact(move |conn| -> Result<serde_json::Value, Status> {
+            // Load existing device if it exists
+            let existing = devices
+                .filter(device_id.eq(&info.device_id))
+                .first::<Device>(conn)
+                .optional()
+                .map_err(|_| Status::InternalServerError)?;
+
+            // Ensure last_checkin timestamp is updated
+            let now = Utc::now();
+            let mut updated = NewDevice::from_device_info(&info.device_id, &info, existing.as_ref());
+            updated.last_checkin = now.naive_utc();
+
+            // Insert or update device
+            diesel::insert_into(devices)
+                .values(&updated)
+                .on_conflict(device_id)
+                .do_update()
+                .set(&updated)
+                .execute(conn)
+                .map_err(|_| Status::InternalServerError)?;
+
+            // Log audit
+            log_audit(conn, &username, "register_or_update_device", Some(&info.device_id), Some("Device registered or updated"))
+                .map_err(|_| Status::InternalServerError)?;
+
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)??;
+
+    Ok(Json(result))
+}
@@ -1,7 +1,7 @@
 use rocket::{get, State, http::Status};
 use rocket::serde::json::Json;
 use diesel::prelude::*;
-use crate::db::pool::DbPool;
+use crate::db::DbPool;
 use crate::models::HistoryLog;
 use crate::schema::history_log::dsl::*;
 
@@ -10,14 +10,12 @@ use crate::schema::history_log::dsl::*;
 pub async fn api_history(
     pool: &State<DbPool>,
 ) -> Result<Json<Vec<HistoryLog>>, Status> {
-    let pool = pool.inner().clone();
-
-    rocket::tokio::task::spawn_blocking(move || {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
 
+    conn.interact(|conn| {
         history_log
             .order(created_at.desc())
-            .load::<HistoryLog>(&mut conn) // type annotation fixes type inference
+            .load::<HistoryLog>(conn) // type annotation fixes type inference
             .map(Json)
             .map_err(|_| Status::InternalServerError)
     })
@@ -0,0 +1,74 @@
+//! Live log tail: the dashboard opens an SSE connection per device and the
+//! agent's `log_tail` action (see `patchpilot_client::log_tail`) posts
+//! appended chunks here as they're polled off disk. Mirrors the reverse
+//! relay in `relay.rs`, just keyed for log viewers instead of command
+//! delivery.
+use dashmap::DashMap;
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::tokio::sync::mpsc::{self, UnboundedSender};
+use rocket::{post, get, State};
+use rocket::http::Status;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Registry of live dashboard log viewers, keyed by `device_id`.
+pub struct LogTailRegistry {
+    channels: DashMap<String, UnboundedSender<Value>>,
+}
+
+impl LogTailRegistry {
+    pub fn new() -> Self {
+        Self {
+            channels: DashMap::new(),
+        }
+    }
+}
+
+impl Default for LogTailRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LogChunk {
+    session_id: String,
+    chunk: String,
+}
+
+/// Dashboard opens this to watch a device's log in real time.
+#[get("/devices/<device_id_param>/logs/tail")]
+pub async fn logs_tail_connect(
+    registry: &State<std::sync::Arc<LogTailRegistry>>,
+    device_id_param: &str,
+) -> EventStream![] {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    registry.channels.insert(device_id_param.to_string(), tx);
+    let device_id_param = device_id_param.to_string();
+    let registry = registry.inner().clone();
+
+    EventStream! {
+        while let Some(payload) = rx.recv().await {
+            yield Event::json(&payload);
+        }
+        registry.channels.remove(&device_id_param);
+    }
+}
+
+/// Agent posts a newly-appended log chunk here.
+#[post("/devices/<device_id_param>/logs/tail/frame", data = "<chunk>")]
+pub fn logs_tail_frame(
+    registry: &State<std::sync::Arc<LogTailRegistry>>,
+    device_id_param: &str,
+    chunk: Json<LogChunk>,
+) -> Status {
+    let chunk = chunk.into_inner();
+    if let Some(tx) = registry.channels.get(device_id_param) {
+        let _ = tx.send(serde_json::json!({
+            "session_id": chunk.session_id,
+            "chunk": chunk.chunk,
+        }));
+    }
+    Status::Ok
+}
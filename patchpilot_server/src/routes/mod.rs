@@ -8,6 +8,14 @@ pub mod pages;
 pub mod auth;
 pub mod users_groups;
 pub mod roles;
+pub mod relay;
+pub mod shell;
+pub mod logs;
+pub mod watch;
+pub mod audit;
+pub mod token_auth;
+pub mod auth_request;
+pub mod storage;
 
 /// API routes
 pub fn api_routes() -> Vec<Route> {
@@ -18,12 +26,18 @@ pub fn api_routes() -> Vec<Route> {
         devices::approve_device,
         devices::register_or_update_device,
         devices::heartbeat,    // heartbeat route from devices.rs
+        devices::issue_nonce,
+        devices::exchange_device_token,
+        devices::revoke_device_token,
+        devices::latency_history,
 
         // Actions
         actions::submit_action,
         actions::report_action_result,
         actions::list_actions,
         actions::cancel_action,
+        actions::command_status,
+        actions::report_command_error,
 
         // History
         history::api_history,
@@ -31,6 +45,43 @@ pub fn api_routes() -> Vec<Route> {
         // Settings
         settings::view_settings,
         settings::update_settings,
+        settings::settings_history,
+        settings::rollback_settings,
+        settings::list_monitor_targets,
+        settings::monitor_target_history,
+        settings::add_monitor_target,
+        settings::remove_monitor_target,
+
+        // Reverse relay
+        relay::relay_connect,
+        relay::relay_ping,
+
+        // Interactive PTY shell sessions
+        shell::shell_control,
+        shell::shell_control_poll,
+        shell::shell_frame,
+
+        // Live log tail
+        logs::logs_tail_connect,
+        logs::logs_tail_frame,
+
+        // Filesystem watch sessions
+        watch::watch_events,
+        watch::watch_status,
+
+        // Audit log integrity
+        audit::verify_audit,
+
+        // Device-approval handshake ("login with device")
+        auth_request::submit_auth_request,
+        auth_request::poll_auth_request,
+        auth_request::list_auth_requests,
+        auth_request::decide_auth_request,
+
+        // Patch artifact storage
+        storage::upload_artifact,
+        storage::download_artifact,
+        storage::list_artifacts,
     ]
 }
 
@@ -46,12 +97,17 @@ pub fn page_routes() -> Vec<Route> {
     ]
 }
 
-/// Auth routes (login/logout)
+/// Auth routes (login/logout, plus the token-based flow for headless agents)
 pub fn auth_routes() -> Vec<Route> {
     routes![
         auth::login_page,
         auth::login,
-        auth::logout
+        auth::login_start,
+        auth::login_finish,
+        auth::logout,
+        token_auth::issue_token,
+        token_auth::refresh,
+        token_auth::revoke_token,
     ]
 }
 
@@ -61,6 +117,8 @@ pub fn users_groups_routes() -> Vec<Route> {
         users_groups::list_users_groups,
         users_groups::add_user,
         users_groups::delete_user,
+        users_groups::block_user,
+        users_groups::unblock_user,
         users_groups::add_group,
         users_groups::delete_group
     ]
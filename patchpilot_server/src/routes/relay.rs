@@ -0,0 +1,51 @@
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::json::Json;
+use rocket::{get, post, State};
+use rocket::http::Status;
+
+use crate::state::AppState;
+use crate::models::NewAction;
+
+/// Long-lived SSE connection an adopted agent keeps open so the server can
+/// push actions to it the moment they're created, instead of the agent
+/// waiting for its next heartbeat poll.
+#[get("/devices/<device_id_param>/relay")]
+pub async fn relay_connect(
+    state: &State<AppState>,
+    device_id_param: &str,
+) -> EventStream![] {
+    let mut rx = state.relay.register(device_id_param);
+    let device_id_param = device_id_param.to_string();
+    let relay = state.relay.clone();
+
+    EventStream! {
+        loop {
+            match rx.recv().await {
+                Some(payload) => yield Event::json(&payload),
+                None => break,
+            }
+        }
+        relay.unregister(&device_id_param);
+    }
+}
+
+/// Fan an already-persisted action out to a single device's relay channel
+/// immediately, bypassing the heartbeat-driven delivery path. Used by
+/// `actions::submit_action` right after the insert succeeds.
+pub fn push_action(state: &AppState, device_id: &str, action: &NewAction) -> bool {
+    let payload = serde_json::json!({
+        "id": action.id,
+        "action_type": action.action_type,
+        "parameters": action.parameters,
+        "created_at": action.created_at.and_utc().to_rfc3339(),
+        "expires_at": action.expires_at.and_utc().to_rfc3339(),
+    });
+    state.relay.push(device_id, payload)
+}
+
+/// Manual trigger endpoint for operators to confirm a device's relay is up
+/// (mostly useful from the dashboard/diagnostics page).
+#[post("/devices/<device_id_param>/relay/ping")]
+pub fn relay_ping(state: &State<AppState>, device_id_param: &str) -> Result<Json<bool>, Status> {
+    Ok(Json(state.relay.is_connected(device_id_param)))
+}
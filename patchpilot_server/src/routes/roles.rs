@@ -14,18 +14,21 @@ pub struct RoleForm {
 
 // List all roles
 #[get("/roles")]
-pub fn list_roles(user: AuthUser, pool: &State<DbPool>) -> Template {
+pub async fn list_roles(user: AuthUser, pool: &State<DbPool>) -> Template {
     if !user.has_role(&UserRole::Admin) {
         return Template::render("unauthorized", &());
     }
 
-    let conn = match pool.get() {
+    let conn = match pool.get().await {
         Ok(c) => c,
         Err(_) => return Template::render("error", &"DB connection failed"),
     };
 
-    let all_roles = roles::table
-        .load::<(i32, String)>(&conn)
+    let all_roles = conn
+        .interact(|conn| roles::table.load::<(i32, String)>(conn))
+        .await
+        .ok()
+        .and_then(|r| r.ok())
         .unwrap_or_default();
 
     Template::render("roles", &all_roles)
@@ -33,53 +36,72 @@ pub fn list_roles(user: AuthUser, pool: &State<DbPool>) -> Template {
 
 // Add role
 #[post("/roles/add", data = "<form>")]
-pub fn add_role(user: AuthUser, pool: &State<DbPool>, form: Form<RoleForm>) -> Redirect {
+pub async fn add_role(user: AuthUser, pool: &State<DbPool>, form: Form<RoleForm>) -> Redirect {
     if !user.has_role(&UserRole::Admin) {
         return Redirect::to("/unauthorized");
     }
 
-    let mut conn = match pool.get() {
+    let conn = match pool.get().await {
         Ok(c) => c,
         Err(_) => return Redirect::to("/error"),
     };
 
-    if diesel::insert_into(roles::table)
-        .values(roles::name.eq(&form.name))
-        .execute(&mut conn)
-        .is_err()
-    {
+    let username = user.username.clone();
+    let role_name = form.name.clone();
+
+    let ok = conn
+        .interact(move |conn| {
+            if diesel::insert_into(roles::table)
+                .values(roles::name.eq(&role_name))
+                .execute(conn)
+                .is_err()
+            {
+                return false;
+            }
+
+            log_audit(conn, &username, "add_role", Some(&role_name), None);
+            true
+        })
+        .await
+        .unwrap_or(false);
+
+    if !ok {
         return Redirect::to("/error");
     }
 
-    log_audit(&mut conn, &user.username, "add_role", Some(&form.name), None);
-
     Redirect::to("/roles")
 }
 
 // Delete role
 #[delete("/roles/<role_id>")]
-pub fn delete_role(user: AuthUser, pool: &State<DbPool>, role_id: i32) -> Redirect {
+pub async fn delete_role(user: AuthUser, pool: &State<DbPool>, role_id: i32) -> Redirect {
     if !user.has_role(&UserRole::Admin) {
         return Redirect::to("/unauthorized");
     }
 
-    let mut conn = match pool.get() {
+    let conn = match pool.get().await {
         Ok(c) => c,
         Err(_) => return Redirect::to("/error"),
     };
 
-    let role_name = roles::table
-        .filter(roles::id.eq(role_id))
-        .select(roles::name)
-        .first::<String>(&mut conn)
-        .unwrap_or_else(|_| "unknown".to_string());
+    let username = user.username.clone();
+
+    conn.interact(move |conn| {
+        let role_name = roles::table
+            .filter(roles::id.eq(role_id))
+            .select(roles::name)
+            .first::<String>(conn)
+            .unwrap_or_else(|_| "unknown".to_string());
 
-    let _ = diesel::delete(user_roles::table.filter(user_roles::role_id.eq(role_id)))
-        .execute(&mut conn);
-    let _ = diesel::delete(roles::table.filter(roles::id.eq(role_id)))
-        .execute(&mut conn);
+        let _ = diesel::delete(user_roles::table.filter(user_roles::role_id.eq(role_id)))
+            .execute(conn);
+        let _ = diesel::delete(roles::table.filter(roles::id.eq(role_id)))
+            .execute(conn);
 
-    log_audit(&mut conn, &user.username, "delete_role", Some(&role_name), None);
+        log_audit(conn, &username, "delete_role", Some(&role_name), None);
+    })
+    .await
+    .ok();
 
     Redirect::to("/roles")
 }
@@ -1,11 +1,14 @@
-use rocket::{get, post, State, form::Form};
+use rocket::{get, post, delete, State, form::Form};
 use rocket::http::Status;
+use rocket::serde::json::Json;
 use crate::state::AppState;
-use crate::auth::AuthUser;
-use crate::routes::history::log_audit;
-use diesel::prelude::*;
-use crate::schema::server_settings;
-use crate::db;
+use crate::auth::{AuthUser, UserRole};
+use crate::db::{self, log_audit};
+use crate::models::{MonitorResult, PingTarget};
+use crate::settings::{
+    ServerSettings, ServerSettingsPatch, SettingsAuthError, SettingsHistoryRecord, SettingsPrincipal,
+    SettingsValidationError,
+};
 
 /// Struct representing form submission for server settings
 #[derive(FromForm)]
@@ -15,7 +18,6 @@ pub struct ServerSettingsForm {
     pub auto_refresh_seconds: Option<i64>,
     pub default_action_ttl_seconds: Option<i64>,
     pub action_polling_enabled: Option<bool>,
-    pub ping_target_ip: Option<String>,
 }
 
 #[get("/settings")]
@@ -25,12 +27,12 @@ pub async fn view_settings(
 ) -> Result<rocket_dyn_templates::Template, Status> {
     let pool = state.system.db_pool.clone();
 
-    let settings = rocket::tokio::task::spawn_blocking(move || {
-        let mut conn = pool.get().map_err(|_| Status::InternalServerError)?;
-        db::load_settings(&mut conn).map_err(|_| Status::InternalServerError)
-    })
-    .await
-    .map_err(|_| Status::InternalServerError)??;
+    let conn = db::get_conn(&pool).await.map_err(|_| Status::InternalServerError)?;
+    let settings = conn
+        .interact(|conn| db::load_settings(conn))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
 
     let mut context = std::collections::HashMap::new();
     context.insert("settings", settings);
@@ -44,69 +46,247 @@ pub async fn update_settings(
     form: Form<ServerSettingsForm>,
     user: AuthUser,
 ) -> Status {
+    if !user.has_role(&UserRole::Admin) {
+        return Status::Unauthorized;
+    }
+
+    let principal = SettingsPrincipal::from(&user);
     let username = user.username.clone();
     let form = form.into_inner();
 
     let pool = state.system.db_pool.clone();
     let settings_arc = state.settings.clone();
+    let dirty = state.settings_dirty.clone();
 
-    rocket::tokio::task::spawn_blocking(move || {
-        if let Ok(mut conn) = pool.get() {
-            let mut settings = db::load_settings(&mut conn).unwrap_or_default();
+    let patch = ServerSettingsPatch {
+        auto_approve_devices: form.auto_approve_devices,
+        auto_refresh_enabled: form.auto_refresh_enabled,
+        auto_refresh_seconds: form.auto_refresh_seconds,
+        default_action_ttl_seconds: form.default_action_ttl_seconds,
+        action_polling_enabled: form.action_polling_enabled,
+    };
 
-            if let Some(v) = form.auto_approve_devices { 
-                let _ = set_auto_approve(&mut conn, v);
-                settings.auto_approve_devices = v;
-            }
-            if let Some(v) = form.auto_refresh_enabled { 
-                let _ = set_auto_refresh(&mut conn, v);
-                settings.auto_refresh_enabled = v;
-            }
-            if let Some(v) = form.auto_refresh_seconds { 
-                let _ = set_auto_refresh_interval(&mut conn, v);
-                settings.auto_refresh_seconds = v;
-            }
-            if let Some(v) = form.default_action_ttl_seconds { settings.default_action_ttl_seconds = v; }
-            if let Some(v) = form.action_polling_enabled { settings.action_polling_enabled = v; }
-            if let Some(v) = form.ping_target_ip { settings.ping_target_ip = v; }
+    let result = match ServerSettings::update(&pool, &settings_arc, &dirty, &principal, patch).await {
+        Ok(_) => Status::Ok,
+        Err(e) if e.downcast_ref::<SettingsAuthError>().is_some() => Status::Unauthorized,
+        Err(e) if e.downcast_ref::<SettingsValidationError>().is_some() => Status::BadRequest,
+        Err(_) => return Status::InternalServerError,
+    };
 
-            let _ = db::save_settings(&mut conn, &settings);
+    if let Ok(conn) = db::get_conn(&pool).await {
+        let _ = conn
+            .interact(move |conn| {
+                log_audit(
+                    conn,
+                    &username,
+                    "update_settings",
+                    None,
+                    Some("Updated server settings"),
+                )
+            })
+            .await;
+    }
 
-            if let Ok(mut shared_settings) = settings_arc.write() {
-                *shared_settings = settings.clone();
-            }
+    result
+}
+
+/// API: GET /settings/history?limit=N — recent configuration revisions,
+/// newest first, for the audit trail described in `settings::ServerSettings`.
+#[get("/settings/history?<limit>")]
+pub async fn settings_history(
+    state: &State<AppState>,
+    user: AuthUser,
+    limit: Option<i64>,
+) -> Result<Json<Vec<SettingsHistoryRecord>>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let pool = state.system.db_pool.clone();
 
-            let _ = log_audit(
-                &mut conn,
-                &username,
-                "update_settings",
-                None,
-                Some("Updated server settings"),
-            );
+    ServerSettings::history(&pool, limit.unwrap_or(50))
+        .await
+        .map(Json)
+        .map_err(|_| Status::InternalServerError)
+}
+
+/// API: POST /settings/rollback/<revision> — restore the settings as they
+/// were immediately after `revision`, recorded as a new revision in their
+/// own right so the undo itself shows up in the history.
+#[post("/settings/rollback/<revision>")]
+pub async fn rollback_settings(
+    state: &State<AppState>,
+    user: AuthUser,
+    revision: i32,
+) -> Status {
+    if !user.has_role(&UserRole::Admin) {
+        return Status::Unauthorized;
+    }
+
+    let principal = SettingsPrincipal::from(&user);
+    let username = user.username.clone();
+    let pool = state.system.db_pool.clone();
+    let settings_arc = state.settings.clone();
+    let dirty = state.settings_dirty.clone();
+
+    match ServerSettings::rollback_to(&pool, &settings_arc, &dirty, &principal, revision).await {
+        Ok(_) => {
+            if let Ok(conn) = db::get_conn(&pool).await {
+                let _ = conn
+                    .interact(move |conn| {
+                        log_audit(
+                            conn,
+                            &username,
+                            "rollback_settings",
+                            Some(&revision.to_string()),
+                            Some("Rolled back server settings to a prior revision"),
+                        )
+                    })
+                    .await;
+            }
+            Status::Ok
+        }
+        Err(e) if e.downcast_ref::<diesel::result::Error>().map(|e| matches!(e, diesel::result::Error::NotFound)).unwrap_or(false) => {
+            Status::NotFound
         }
-    })
-    .await
-    .ok();
+        Err(e) if e.downcast_ref::<SettingsAuthError>().is_some() => Status::Unauthorized,
+        Err(_) => Status::InternalServerError,
+    }
+}
+
+/// One entry of the uptime view returned by [`list_monitor_targets`]: a
+/// configured target plus its most recent scan result, if it's been
+/// scanned at least once since being added.
+#[derive(serde::Serialize)]
+pub struct MonitorTargetStatus {
+    #[serde(flatten)]
+    pub target: PingTarget,
+    pub latest: Option<MonitorResult>,
+}
+
+/// Struct representing form submission for adding a monitoring target.
+#[derive(FromForm)]
+pub struct AddMonitorTargetForm {
+    pub name: String,
+    pub address: String,
+}
 
-    Status::Ok
+/// API: GET /settings/monitor/targets — every configured target alongside
+/// its latest reachability/RTT reading, replacing the single
+/// `ping_target_ip` field this subsystem grew out of (see
+/// `tasks::monitor_scan`).
+#[get("/settings/monitor/targets")]
+pub async fn list_monitor_targets(
+    state: &State<AppState>,
+    _user: AuthUser,
+) -> Result<Json<Vec<MonitorTargetStatus>>, Status> {
+    let pool = state.system.db_pool.clone();
+    let conn = db::get_conn(&pool).await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(db::latest_monitor_results)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map(|rows| {
+            Json(
+                rows.into_iter()
+                    .map(|(target, latest)| MonitorTargetStatus { target, latest })
+                    .collect(),
+            )
+        })
+        .map_err(|_| Status::InternalServerError)
 }
 
-/* Direct DB setters now actively used in update_settings */
+/// API: GET /settings/monitor/targets/<target_id>/history?limit=N —
+/// a single target's scan history, newest first, for an uptime chart.
+#[get("/settings/monitor/targets/<target_id>/history?<limit>")]
+pub async fn monitor_target_history(
+    state: &State<AppState>,
+    _user: AuthUser,
+    target_id: i32,
+    limit: Option<i64>,
+) -> Result<Json<Vec<MonitorResult>>, Status> {
+    let pool = state.system.db_pool.clone();
+    let conn = db::get_conn(&pool).await.map_err(|_| Status::InternalServerError)?;
 
-pub fn set_auto_approve(conn: &mut SqliteConnection, value: bool) -> QueryResult<usize> {
-    diesel::update(server_settings::table)
-        .set(server_settings::auto_approve_devices.eq(value))
-        .execute(conn)
+    conn.interact(move |conn| db::monitor_history_for_target(conn, target_id, limit.unwrap_or(100)))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map(Json)
+        .map_err(|_| Status::InternalServerError)
 }
 
-pub fn set_auto_refresh(conn: &mut SqliteConnection, value: bool) -> QueryResult<usize> {
-    diesel::update(server_settings::table)
-        .set(server_settings::auto_refresh_enabled.eq(value))
-        .execute(conn)
+/// API: POST /settings/monitor/targets — add a new target to the
+/// connectivity monitor. Admin-only, same as the other settings mutators.
+#[post("/settings/monitor/targets", data = "<form>")]
+pub async fn add_monitor_target(
+    state: &State<AppState>,
+    user: AuthUser,
+    form: Form<AddMonitorTargetForm>,
+) -> Result<Json<PingTarget>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let username = user.username.clone();
+    let form = form.into_inner();
+    let pool = state.system.db_pool.clone();
+    let conn = db::get_conn(&pool).await.map_err(|_| Status::InternalServerError)?;
+
+    let target = conn
+        .interact(move |conn| db::add_ping_target(conn, &form.name, &form.address))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    if let Ok(conn) = db::get_conn(&pool).await {
+        let target_address = target.address.clone();
+        let _ = conn
+            .interact(move |conn| {
+                log_audit(conn, &username, "add_monitor_target", Some(&target_address), None)
+            })
+            .await;
+    }
+
+    Ok(Json(target))
 }
 
-pub fn set_auto_refresh_interval(conn: &mut SqliteConnection, value: i64) -> QueryResult<usize> {
-    diesel::update(server_settings::table)
-        .set(server_settings::auto_refresh_seconds.eq(value))
-        .execute(conn)
+/// API: DELETE /settings/monitor/targets/<target_id> — remove a target and
+/// its recorded history. Admin-only.
+#[delete("/settings/monitor/targets/<target_id>")]
+pub async fn remove_monitor_target(
+    state: &State<AppState>,
+    user: AuthUser,
+    target_id: i32,
+) -> Status {
+    if !user.has_role(&UserRole::Admin) {
+        return Status::Unauthorized;
+    }
+
+    let username = user.username.clone();
+    let pool = state.system.db_pool.clone();
+    let conn = match db::get_conn(&pool).await {
+        Ok(conn) => conn,
+        Err(_) => return Status::InternalServerError,
+    };
+
+    match conn.interact(move |conn| db::remove_ping_target(conn, target_id)).await {
+        Ok(Ok(0)) => Status::NotFound,
+        Ok(Ok(_)) => {
+            if let Ok(conn) = db::get_conn(&pool).await {
+                let _ = conn
+                    .interact(move |conn| {
+                        log_audit(
+                            conn,
+                            &username,
+                            "remove_monitor_target",
+                            Some(&target_id.to_string()),
+                            None,
+                        )
+                    })
+                    .await;
+            }
+            Status::Ok
+        }
+        _ => Status::InternalServerError,
+    }
 }
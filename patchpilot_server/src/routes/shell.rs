@@ -0,0 +1,117 @@
+//! Interactive PTY shell sessions. An operator opens a session by submitting
+//! a regular `"pty"` action (see `actions::submit_action`); once the agent
+//! picks it up, control frames (stdin/resize/kill) flow operator -> agent
+//! through `ShellControlQueue`, and output/exit frames flow agent -> server
+//! here. Only exit frames are persisted, into the same `action_targets` row
+//! the session's action created; output frames are accepted and dropped.
+use dashmap::DashMap;
+use diesel::prelude::*;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, post, State};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::db::DbPool;
+use crate::schema::action_targets::{self, action_id as at_action_id, device_id as at_device_id, last_update, response, status};
+
+/// Per-session queues of control frames an operator has sent but the agent
+/// hasn't polled for yet.
+pub struct ShellControlQueue {
+    pending: DashMap<String, Mutex<VecDeque<Value>>>,
+}
+
+impl ShellControlQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: DashMap::new(),
+        }
+    }
+
+    pub fn push(&self, session_id: &str, frame: Value) {
+        self.pending
+            .entry(session_id.to_string())
+            .or_insert_with(|| Mutex::new(VecDeque::new()))
+            .lock()
+            .unwrap()
+            .push_back(frame);
+    }
+
+    /// Drain whatever's queued for this session right now.
+    pub fn drain(&self, session_id: &str) -> Vec<Value> {
+        match self.pending.get(session_id) {
+            Some(queue) => queue.lock().unwrap().drain(..).collect(),
+            None => vec![],
+        }
+    }
+}
+
+impl Default for ShellControlQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Operator sends a stdin/resize/kill frame down to the agent's session.
+#[post("/devices/<_device_id_param>/shell/<session_id_param>/control", data = "<frame>")]
+pub fn shell_control(
+    queue: &State<std::sync::Arc<ShellControlQueue>>,
+    _device_id_param: &str,
+    session_id_param: &str,
+    frame: Json<Value>,
+) -> Status {
+    queue.push(session_id_param, frame.into_inner());
+    Status::Accepted
+}
+
+/// Agent polls for any control frames queued since its last check.
+#[get("/devices/<_device_id_param>/shell/<session_id_param>/control/poll")]
+pub fn shell_control_poll(
+    queue: &State<std::sync::Arc<ShellControlQueue>>,
+    _device_id_param: &str,
+    session_id_param: &str,
+) -> Json<Vec<Value>> {
+    Json(queue.drain(session_id_param))
+}
+
+/// Agent posts an output or exit frame for a running session. Exit frames
+/// record the final code into the session's `action_targets.response`.
+#[post("/devices/<device_id_param>/shell/<session_id_param>/frame", data = "<frame>")]
+pub async fn shell_frame(
+    pool: &State<DbPool>,
+    device_id_param: &str,
+    session_id_param: &str,
+    frame: Json<Value>,
+) -> Result<Status, Status> {
+    let frame = frame.into_inner();
+    if frame.get("type").and_then(Value::as_str) != Some("exit") {
+        // Output frames are transient terminal data; persisting every
+        // keystroke's worth isn't useful, so only exit frames are durable.
+        return Ok(Status::Ok);
+    }
+
+    let code = frame.get("code").and_then(Value::as_i64).unwrap_or(-1);
+    let device_id_param = device_id_param.to_string();
+    let session_id_param = session_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    conn.interact(move |conn| -> Result<Status, Status> {
+        diesel::update(
+            action_targets::table
+                .filter(at_action_id.eq(&session_id_param))
+                .filter(at_device_id.eq(&device_id_param)),
+        )
+        .set((
+            status.eq("completed"),
+            last_update.eq(chrono::Utc::now().naive_utc()),
+            response.eq(Some(code.to_string())),
+        ))
+        .execute(conn)
+        .map_err(|_| Status::InternalServerError)?;
+
+        Ok(Status::Ok)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+}
@@ -0,0 +1,80 @@
+//! Upload/download for patch artifacts, backed by whichever
+//! [`crate::storage::ObjectStore`] `AppState::storage` was built with. Keys
+//! are caller-supplied paths (e.g. `patches/<name>/<version>.bin`) — the
+//! filesystem backend is the one that actually enforces they can't climb
+//! out of its root (see `storage::FilesystemStore::resolve`).
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::response::content::RawJson;
+use rocket::{get, put, State};
+use std::sync::Arc;
+
+use crate::auth::{AuthUser, UserRole};
+use crate::state::AppState;
+
+/// Cap on a single uploaded patch artifact.
+const MAX_ARTIFACT_SIZE: rocket::data::ByteUnit = rocket::data::ByteUnit::Mebibyte(256);
+
+#[put("/artifacts/<key..>", data = "<body>")]
+pub async fn upload_artifact(
+    user: AuthUser,
+    key: std::path::PathBuf,
+    body: Data<'_>,
+    app_state: &State<Arc<AppState>>,
+) -> Result<Status, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let bytes = body
+        .open(MAX_ARTIFACT_SIZE)
+        .into_bytes()
+        .await
+        .map_err(|_| Status::BadRequest)?;
+
+    app_state
+        .storage
+        .put(&key.to_string_lossy(), bytes.into_inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Status::Created)
+}
+
+#[get("/artifacts/<key..>")]
+pub async fn download_artifact(
+    user: AuthUser,
+    key: std::path::PathBuf,
+    app_state: &State<Arc<AppState>>,
+) -> Result<Vec<u8>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    app_state
+        .storage
+        .get(&key.to_string_lossy())
+        .await
+        .map_err(|_| Status::NotFound)
+}
+
+#[get("/artifacts?<prefix>")]
+pub async fn list_artifacts(
+    user: AuthUser,
+    prefix: Option<String>,
+    app_state: &State<Arc<AppState>>,
+) -> Result<RawJson<String>, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let keys = app_state
+        .storage
+        .list(prefix.as_deref().unwrap_or(""))
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(RawJson(
+        serde_json::to_string(&keys).unwrap_or_else(|_| "[]".to_string()),
+    ))
+}
@@ -0,0 +1,153 @@
+//! JSON token-based login for headless/agent callers — the `TokenAuth`
+//! counterpart to `routes::auth`'s cookie-based browser login. See
+//! `crate::token_auth` for the guard and token machinery itself.
+use diesel::prelude::*;
+use diesel::SelectableHelper;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{post, State};
+use std::sync::Arc;
+
+use crate::auth::{verify_password, AuthUser, UserRole};
+use crate::db::DbPool;
+use crate::schema::{roles, user_roles, users};
+use crate::state::AppState;
+use crate::token_auth;
+
+#[derive(serde::Deserialize)]
+pub struct TokenLoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct TokenPairResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+struct UserRow {
+    pub id: i32,
+    pub username: String,
+    pub password_hash: String,
+}
+
+/// Exchange a username/password for an access+refresh token pair.
+#[post("/token", data = "<body>")]
+pub async fn issue_token(
+    body: Json<TokenLoginRequest>,
+    pool: &State<DbPool>,
+    app_state: &State<Arc<AppState>>,
+) -> Result<Json<TokenPairResponse>, Status> {
+    let body = body.into_inner();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let authenticated = conn
+        .interact(move |conn| -> Option<(UserRow, Vec<String>)> {
+            let user = users::table
+                .filter(users::username.eq(&body.username))
+                .select(UserRow::as_select())
+                .first::<UserRow>(conn)
+                .optional()
+                .unwrap_or(None)?;
+
+            if !verify_password(&body.password, &user.password_hash) {
+                return None;
+            }
+
+            let role_names = user_roles::table
+                .inner_join(roles::table.on(roles::id.eq(user_roles::role_id)))
+                .filter(user_roles::user_id.eq(user.id))
+                .select(roles::name)
+                .load::<String>(conn)
+                .unwrap_or_default();
+
+            Some((user, role_names))
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let (user, role_names) = authenticated.ok_or(Status::Unauthorized)?;
+
+    let (access_token, refresh_token) =
+        token_auth::issue_token_pair(pool, app_state, user.id, &user.username, &role_names).await?;
+
+    Ok(Json(TokenPairResponse { access_token, refresh_token }))
+}
+
+/// Exchange a still-valid refresh token for a new access+refresh pair,
+/// revoking the old refresh token in the process (rotation, not reuse).
+#[post("/refresh", data = "<body>")]
+pub async fn refresh(
+    body: Json<RefreshRequest>,
+    pool: &State<DbPool>,
+    app_state: &State<Arc<AppState>>,
+) -> Result<Json<TokenPairResponse>, Status> {
+    let token_hash = token_auth::hash_refresh_token(&body.refresh_token);
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let hash_for_lookup = token_hash.clone();
+    let found = conn
+        .interact(move |conn| -> Result<_, diesel::result::Error> {
+            let token_row = crate::db::find_valid_refresh_token(conn, &hash_for_lookup)?;
+
+            let Some(token_row) = token_row else {
+                return Ok(None);
+            };
+
+            crate::db::touch_refresh_token(conn, token_row.id)?;
+            crate::db::revoke_refresh_token(conn, token_row.id)?;
+
+            let username: String = users::table
+                .filter(users::id.eq(token_row.user_id))
+                .select(users::username)
+                .first(conn)?;
+
+            let role_names = user_roles::table
+                .inner_join(roles::table.on(roles::id.eq(user_roles::role_id)))
+                .filter(user_roles::user_id.eq(token_row.user_id))
+                .select(roles::name)
+                .load::<String>(conn)
+                .unwrap_or_default();
+
+            Ok(Some((token_row.user_id, username, role_names)))
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    let (user_id, username, role_names) = found.ok_or(Status::Unauthorized)?;
+
+    let (access_token, refresh_token) =
+        token_auth::issue_token_pair(pool, app_state, user_id, &username, &role_names).await?;
+
+    Ok(Json(TokenPairResponse { access_token, refresh_token }))
+}
+
+/// Admin-only: revoke a specific refresh token by id, so a lost or
+/// decommissioned device can be cut off before its access token even
+/// expires. Guarded by the cookie session, not `TokenAuth` — this is an
+/// operator action taken from the dashboard, not something an agent does
+/// to itself.
+#[post("/revoke/<token_id>")]
+pub async fn revoke_token(user: AuthUser, token_id: i32, pool: &State<DbPool>) -> Result<Status, Status> {
+    if !user.has_role(&UserRole::Admin) {
+        return Err(Status::Unauthorized);
+    }
+
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    conn.interact(move |conn| crate::db::revoke_refresh_token(conn, token_id))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Status::Ok)
+}
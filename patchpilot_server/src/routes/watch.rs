@@ -0,0 +1,68 @@
+//! Filesystem watch sessions: the agent's `watch` action (see
+//! `patchpilot_client::watcher`) posts debounced batches of file-change
+//! events here, and polls the paired status route to learn when it should
+//! tear the watch down. Status is derived straight from the `actions`
+//! table's existing `canceled`/`expires_at` columns — no dedicated watch
+//! table needed, same as how `cancel_action` and the action-expiry sweeper
+//! both just flip `canceled`.
+use chrono::Utc;
+use diesel::prelude::*;
+use rocket::serde::json::Json;
+use rocket::{get, post, http::Status, State};
+use serde::Deserialize;
+
+use crate::db::DbPool;
+use crate::schema::actions::dsl::*;
+
+#[derive(Deserialize)]
+pub struct FileChangeEvent {
+    path: String,
+    kind: String,
+    timestamp: String,
+}
+
+/// Agent posts a debounced batch of filesystem changes for a live watch.
+#[post("/devices/<device_id_param>/watch/<action_id_param>/events", data = "<batch>")]
+pub fn watch_events(
+    device_id_param: &str,
+    action_id_param: &str,
+    batch: Json<Vec<FileChangeEvent>>,
+) -> Status {
+    for event in batch.into_inner() {
+        tracing::info!(
+            "watch[{}] device={} {} {} at {}",
+            action_id_param, device_id_param, event.kind, event.path, event.timestamp
+        );
+    }
+    Status::Ok
+}
+
+/// Agent polls this to learn whether its watch action has been canceled or
+/// has expired, so it can stop watching instead of running forever.
+#[get("/devices/<_device_id_param>/watch/<action_id_param>/status")]
+pub async fn watch_status(
+    pool: &State<DbPool>,
+    _device_id_param: &str,
+    action_id_param: &str,
+) -> Result<Json<serde_json::Value>, Status> {
+    let action_id_str = action_id_param.to_string();
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+
+    let active = conn
+        .interact(move |conn| -> Result<bool, Status> {
+            let action = actions
+                .filter(id.eq(&action_id_str))
+                .first::<crate::models::Action>(conn)
+                .optional()
+                .map_err(|_| Status::InternalServerError)?;
+
+            Ok(match action {
+                Some(a) => !a.canceled && a.expires_at > Utc::now().naive_utc(),
+                None => false,
+            })
+        })
+        .await
+        .map_err(|_| Status::InternalServerError)??;
+
+    Ok(Json(serde_json::json!({ "active": active })))
+}
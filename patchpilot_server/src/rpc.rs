@@ -0,0 +1,210 @@
+//! Cap'n Proto control channel: the typed counterpart to the JSON
+//! `POST /api/devices/heartbeat` route, for agents built against
+//! `schemas/control.capnp`. Runs its own TCP listener (Cap'n Proto RPC
+//! frames its own connections; it isn't layered over HTTP) rather than
+//! going through Rocket, and is registered with the same
+//! [`BackgroundRunner`](crate::background::BackgroundRunner) every other
+//! long-lived worker uses so it shuts down cleanly with the rest of them.
+use std::sync::Arc;
+
+use capnp::capability::Promise;
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+use futures::AsyncReadExt;
+use rocket::tokio::net::TcpListener;
+use rocket::tokio::{self, select};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::capnp_schema::{control_capnp, device_report_capnp};
+use crate::db::DbPool;
+use crate::device_auth::validate_device_access_token;
+use crate::state::AppState;
+use crate::storage::store_system_info_snapshot;
+
+/// Port the control channel listens on, independent of Rocket's HTTP port.
+/// Overridable via `PATCHPILOT_RPC_ADDR` so it doesn't collide in tests or
+/// multi-instance deployments.
+fn listen_addr() -> String {
+    std::env::var("PATCHPILOT_RPC_ADDR").unwrap_or_else(|_| "0.0.0.0:9100".to_string())
+}
+
+/// Whether the capnp control channel should start at all. Unlike the JSON
+/// heartbeat route, this listener accepts raw TCP with no mTLS in front of
+/// it, so it's opt-in rather than always-on — an operator who wants the
+/// typed channel turns it on deliberately instead of it silently listening
+/// on every deployment.
+pub fn enabled() -> bool {
+    std::env::var("PATCHPILOT_RPC_ENABLED")
+        .map(|v| matches!(v.as_str(), "1" | "true" | "TRUE" | "yes"))
+        .unwrap_or(false)
+}
+
+struct ControlImpl {
+    pool: DbPool,
+    app_state: Arc<AppState>,
+}
+
+impl control_capnp::control::Server for ControlImpl {
+    fn report(
+        &mut self,
+        params: control_capnp::control::ReportParams,
+        mut results: control_capnp::control::ReportResults,
+    ) -> Promise<(), capnp::Error> {
+        let params = pry!(params.get());
+        let device_id_val = pry!(pry!(params.get_device_id()).to_string());
+        let access_token = pry!(pry!(params.get_access_token()).to_string());
+        let report = pry!(params.get_report());
+        let report_json = pry!(device_report_to_json(report));
+
+        let pool = self.pool.clone();
+        let app_state = self.app_state.clone();
+
+        // Same bearer-token requirement the JSON heartbeat route enforces
+        // via `DeviceAuth` — a bare client-supplied `device_id` is not
+        // enough to read or act on that device's pending actions. The
+        // token's own `device_id` is what's trusted for the lookup below,
+        // not the caller-supplied one, so a valid token for one device
+        // can't be replayed against another device's id.
+        let authenticated_device_id = match validate_device_access_token(&access_token, &app_state)
+        {
+            Some(id) if id == device_id_val => id,
+            _ => {
+                return Promise::err(capnp::Error::failed(
+                    "invalid or mismatched device access token".to_string(),
+                ))
+            }
+        };
+
+        Promise::from_future(async move {
+            // Best-effort, same as the JSON heartbeat's snapshot write — a
+            // storage hiccup shouldn't fail the RPC the agent is waiting on.
+            if let Err(e) =
+                store_system_info_snapshot(&app_state.storage, &authenticated_device_id, &report_json).await
+            {
+                tracing::warn!(
+                    "Failed to persist capnp device report for {}: {}",
+                    authenticated_device_id,
+                    e
+                );
+            }
+
+            let conn = pool
+                .get()
+                .await
+                .map_err(|e| capnp::Error::failed(e.to_string()))?;
+
+            let lookup_device_id = authenticated_device_id.clone();
+            let next = conn
+                .interact(move |conn| crate::db::next_pending_action(conn, &lookup_device_id))
+                .await
+                .map_err(|e| capnp::Error::failed(e.to_string()))?
+                .map_err(|e| capnp::Error::failed(e.to_string()))?;
+
+            let mut action = results.get().init_action();
+            if let Some((action_id_val, action_type_val, parameters_val)) = next {
+                action.set_action_id(&action_id_val);
+                action.set_command(&action_type_val);
+
+                let argv: Vec<String> = parameters_val.into_iter().collect();
+                let mut argv_list = action.init_argv(argv.len() as u32);
+                for (i, arg) in argv.into_iter().enumerate() {
+                    argv_list.set(i as u32, &arg);
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+fn device_report_to_json(
+    report: device_report_capnp::device_report::Reader,
+) -> capnp::Result<serde_json::Value> {
+    let memory = report.get_memory()?;
+    let disks = report
+        .get_disks()?
+        .iter()
+        .map(|disk| {
+            Ok(serde_json::json!({
+                "mount_point": disk.get_mount_point()?.to_string()?,
+                "total_space": disk.get_total_bytes(),
+                "free_space": disk.get_free_bytes(),
+            }))
+        })
+        .collect::<capnp::Result<Vec<_>>>()?;
+
+    Ok(serde_json::json!({
+        "serial_number": report.get_serial_number()?.to_string()?,
+        "os_info": report.get_os_info()?.to_string()?,
+        "cpu": report.get_cpu_usage_percent(),
+        "memory": {
+            "total_memory": memory.get_total_bytes(),
+            "free_memory": memory.get_free_bytes(),
+        },
+        "disks": disks,
+    }))
+}
+
+/// Accept connections on [`listen_addr`] until `cancel` fires, unless
+/// [`enabled`] says the operator hasn't opted in, in which case this is a
+/// no-op. Registered via `BackgroundRunner::spawn_worker` rather than
+/// `register` regardless — it owns a long-lived accept loop, not a periodic
+/// tick, and `spawn_worker`'s bookkeeping shouldn't depend on the flag.
+pub async fn serve(pool: DbPool, app_state: Arc<AppState>, cancel: CancellationToken) {
+    if !enabled() {
+        tracing::info!(
+            "Cap'n Proto control channel disabled (set PATCHPILOT_RPC_ENABLED=true to enable)"
+        );
+        return;
+    }
+
+
+    let addr = listen_addr();
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind capnp control channel on {}: {}", addr, e);
+            return;
+        }
+    };
+
+    tracing::info!("Cap'n Proto control channel listening on {}", addr);
+
+    loop {
+        select! {
+            _ = cancel.cancelled() => {
+                tracing::info!("Cap'n Proto control channel shutting down");
+                return;
+            }
+            accepted = listener.accept() => {
+                let (stream, _peer_addr) = match accepted {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        tracing::warn!("capnp control channel accept failed: {}", e);
+                        continue;
+                    }
+                };
+
+                let pool = pool.clone();
+                let app_state = app_state.clone();
+                tokio::spawn(async move {
+                    let (reader, writer) = stream.compat().split();
+                    let network = twoparty::VatNetwork::new(
+                        reader,
+                        writer,
+                        rpc_twoparty_capnp::Side::Server,
+                        Default::default(),
+                    );
+
+                    let control_client: control_capnp::control::Client =
+                        capnp_rpc::new_client(ControlImpl { pool, app_state });
+
+                    let rpc_system = RpcSystem::new(Box::new(network), Some(control_client.client));
+                    if let Err(e) = rpc_system.await {
+                        tracing::warn!("capnp control channel session ended: {}", e);
+                    }
+                });
+            }
+        }
+    }
+}
@@ -22,14 +22,22 @@ diesel::table! {
 
         network_throughput -> BigInt,
         ping_latency -> Nullable<Float>,
+        probe_results -> Nullable<Text>,
 
-        device_type -> Text,
+        device_type -> Integer,
         device_model -> Text,
         uptime -> Nullable<Text>,
         updates_available -> Bool,
 
         network_interfaces -> Nullable<Text>,
         ip_address -> Nullable<Text>,
+
+        protocol_version -> Nullable<Integer>,
+        capabilities -> Nullable<Text>,
+        protocol_outdated -> Bool,
+
+        public_key -> Nullable<Text>,
+        refresh_token_hash -> Nullable<Text>,
     }
 }
 
@@ -71,11 +79,13 @@ diesel::table! {
 diesel::table! {
     audit (id) {
         id -> Integer,
-        actor -> Text,               
-        action_type -> Text,         
-        target -> Nullable<Text>,    
-        details -> Nullable<Text>,   
+        actor -> Text,
+        action_type -> Text,
+        target -> Nullable<Text>,
+        details -> Nullable<Text>,
         created_at -> Timestamp,
+        prev_hash -> Text,
+        entry_hash -> Text,
     }
 }
 
@@ -86,6 +96,8 @@ diesel::table! {
         username -> Text,
         password_hash -> Text,
         created_at -> Timestamp,
+        opaque_password_file -> Nullable<Binary>,
+        blocked -> Bool,
     }
 }
 
@@ -120,10 +132,120 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    advisories (id) {
+        id -> Integer,
+        guid -> Text,
+        source_url -> Text,
+        title -> Text,
+        link -> Text,
+        os_match -> Nullable<Text>,
+        published_at -> Timestamp,
+        ingested_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    auth_requests (id) {
+        id -> Text,
+        device_id -> Text,
+        request_ip -> Nullable<Text>,
+        public_key -> Text,
+        access_code -> Text,
+        approved -> Nullable<Bool>,
+        encrypted_secret -> Nullable<Text>,
+        created_at -> Timestamp,
+        response_date -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        issued_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked -> Bool,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    nonces (id) {
+        id -> Integer,
+        nonce -> Text,
+        issued_at -> Timestamp,
+        expires_at -> Timestamp,
+        consumed -> Bool,
+    }
+}
+
+diesel::table! {
+    latency_history (id) {
+        id -> Integer,
+        device_id -> Text,
+        server_latency_ms -> Nullable<Float>,
+        recorded_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    server_settings (id) {
+        id -> Integer,
+        auto_approve_devices -> Bool,
+        auto_refresh_enabled -> Bool,
+        auto_refresh_seconds -> BigInt,
+        default_action_ttl_seconds -> BigInt,
+        action_polling_enabled -> Bool,
+        jwt_secret -> Text,
+        access_token_ttl_seconds -> BigInt,
+        storage_backend -> Text,
+        storage_local_path -> Text,
+        s3_endpoint -> Text,
+        s3_bucket -> Text,
+        s3_access_key -> Text,
+        s3_secret_key -> Text,
+        s3_region -> Text,
+    }
+}
+
+diesel::table! {
+    server_settings_history (revision) {
+        revision -> Integer,
+        changed_at -> Timestamp,
+        changed_fields -> Text,
+        old_values -> Text,
+        new_values -> Text,
+    }
+}
+
+diesel::table! {
+    ping_targets (id) {
+        id -> Integer,
+        name -> Text,
+        address -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    monitor_results (id) {
+        id -> Integer,
+        target_id -> Integer,
+        recorded_at -> Timestamp,
+        ipv4_reachable -> Nullable<Bool>,
+        ipv6_reachable -> Nullable<Bool>,
+        rtt_ms -> Nullable<Float>,
+    }
+}
+
 diesel::joinable!(user_roles -> roles (role_id));
 diesel::joinable!(user_roles -> users (user_id));
 diesel::joinable!(user_groups -> users (user_id));
 diesel::joinable!(user_groups -> groups (group_id));
+diesel::joinable!(refresh_tokens -> users (user_id));
+diesel::joinable!(monitor_results -> ping_targets (target_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     devices,
@@ -136,4 +258,13 @@ diesel::allow_tables_to_appear_in_same_query!(
     user_roles,
     groups,
     user_groups,
+    advisories,
+    refresh_tokens,
+    auth_requests,
+    nonces,
+    latency_history,
+    server_settings,
+    server_settings_history,
+    ping_targets,
+    monitor_results,
 );
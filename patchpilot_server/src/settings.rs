@@ -1,9 +1,130 @@
 use serde::{Serialize, Deserialize};
 use diesel::prelude::*;
-use diesel::SqliteConnection;
+use crate::auth::{AuthUser, UserRole};
+use crate::db::{DbBackendConnection, DbPool};
 use crate::db;
-use crate::schema::server_settings;
+use crate::schema::server_settings_history;
 use diesel::result::QueryResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Error type for the pool-backed mutators below: either the pool couldn't
+/// hand out a connection, the blocking `interact` task itself failed, or
+/// the query it ran failed — callers that just want to log-and-continue
+/// can match on this loosely via `Display`, same as `background::WorkerError`.
+pub type SettingsError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The caller every [`ServerSettings`] mutator authorizes against: a
+/// non-blocked account with the `Admin` role. Built from the route's
+/// `AuthUser` (via `From`) rather than checked ad hoc per-route, so a
+/// mutator is a protected resource even if a future caller forgets the
+/// `has_role` check the route handlers already do — settings changes are
+/// sensitive enough to gate twice.
+pub struct SettingsPrincipal {
+    is_admin: bool,
+    blocked: bool,
+}
+
+impl SettingsPrincipal {
+    fn authorize(&self) -> Result<(), SettingsAuthError> {
+        if self.is_admin && !self.blocked {
+            Ok(())
+        } else {
+            Err(SettingsAuthError)
+        }
+    }
+}
+
+impl From<&AuthUser> for SettingsPrincipal {
+    fn from(user: &AuthUser) -> Self {
+        Self {
+            is_admin: user.has_role(&UserRole::Admin),
+            blocked: user.blocked,
+        }
+    }
+}
+
+/// Returned by a [`ServerSettings`] mutator when [`SettingsPrincipal::authorize`]
+/// rejects the caller — a route handler matches this via `downcast_ref`
+/// the same way it already does [`SettingsValidationError`], mapping it to
+/// `Status::Unauthorized`.
+#[derive(Debug)]
+pub struct SettingsAuthError;
+
+impl std::fmt::Display for SettingsAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "settings changes require a non-blocked admin principal")
+    }
+}
+
+impl std::error::Error for SettingsAuthError {}
+
+/// Invariant violated by a [`ServerSettingsPatch`] passed to
+/// [`ServerSettings::update`]. Named per offending field, rather than a
+/// single generic "invalid settings" variant, so a caller (or the route
+/// handler logging it) can report exactly what needs fixing.
+#[derive(Debug)]
+pub enum SettingsValidationError {
+    AutoRefreshSecondsNotPositive(i64),
+    DefaultActionTtlSecondsNotPositive(i64),
+}
+
+impl std::fmt::Display for SettingsValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AutoRefreshSecondsNotPositive(v) => {
+                write!(f, "auto_refresh_seconds must be > 0, got {v}")
+            }
+            Self::DefaultActionTtlSecondsNotPositive(v) => {
+                write!(f, "default_action_ttl_seconds must be > 0, got {v}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsValidationError {}
+
+/// An `Option`-per-field description of a settings change, applied
+/// atomically by [`ServerSettings::update`]: fields left as `None` are
+/// left untouched, so a caller only needs to populate the ones it actually
+/// wants to change.
+#[derive(Default)]
+pub struct ServerSettingsPatch {
+    pub auto_approve_devices: Option<bool>,
+    pub auto_refresh_enabled: Option<bool>,
+    pub auto_refresh_seconds: Option<i64>,
+    pub default_action_ttl_seconds: Option<i64>,
+    pub action_polling_enabled: Option<bool>,
+}
+
+impl ServerSettingsPatch {
+    /// Check the invariants that matter regardless of which fields are
+    /// being changed, before anything is applied — a worker loop later
+    /// divides by `auto_refresh_seconds`, so a bad value here should never
+    /// reach the database.
+    fn validate(&self) -> Result<(), SettingsValidationError> {
+        if let Some(v) = self.auto_refresh_seconds {
+            if v <= 0 {
+                return Err(SettingsValidationError::AutoRefreshSecondsNotPositive(v));
+            }
+        }
+        if let Some(v) = self.default_action_ttl_seconds {
+            if v <= 0 {
+                return Err(SettingsValidationError::DefaultActionTtlSecondsNotPositive(v));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply this patch's `Some` fields onto `settings` in place.
+    fn apply_to(&self, settings: &mut ServerSettings) {
+        if let Some(v) = self.auto_approve_devices { settings.auto_approve_devices = v; }
+        if let Some(v) = self.auto_refresh_enabled { settings.auto_refresh_enabled = v; }
+        if let Some(v) = self.auto_refresh_seconds { settings.auto_refresh_seconds = v; }
+        if let Some(v) = self.default_action_ttl_seconds { settings.default_action_ttl_seconds = v; }
+        if let Some(v) = self.action_polling_enabled { settings.action_polling_enabled = v; }
+    }
+}
 
 /// Struct for server settings
 #[derive(Serialize, Deserialize, Clone)]
@@ -13,41 +134,244 @@ pub struct ServerSettings {
     pub auto_refresh_seconds: i64,
     pub default_action_ttl_seconds: i64,
     pub action_polling_enabled: bool,
-    pub ping_target_ip: String,
+
+    /// HMAC secret used to sign/verify `TokenAuth` access tokens (see
+    /// `token_auth::issue_access_token`). Stored in the `server_settings`
+    /// row and read from there by [`load`](Self::load); `JWT_SECRET` only
+    /// supplies the value when that row can't be loaded (see `Default`).
+    pub jwt_secret: String,
+    /// How long a `TokenAuth` access token stays valid before an agent must
+    /// use its refresh token to mint a new one.
+    pub access_token_ttl_seconds: i64,
+
+    /// Which `ObjectStore` implementation `storage::build_object_store`
+    /// constructs: `"filesystem"` (default) or `"s3"`.
+    pub storage_backend: String,
+    /// Root directory for the filesystem backend.
+    pub storage_local_path: String,
+    /// S3-compatible endpoint URL (e.g. a MinIO deployment). Left empty to
+    /// use AWS's default endpoint for `s3_region`.
+    pub s3_endpoint: String,
+    pub s3_bucket: String,
+    pub s3_access_key: String,
+    pub s3_secret_key: String,
+    pub s3_region: String,
 }
 
 impl ServerSettings {
     /// Load settings from DB, fallback to default
-    pub fn load(conn: &mut SqliteConnection) -> Self {
+    pub fn load(conn: &mut DbBackendConnection) -> Self {
         db::load_settings(conn).unwrap_or_else(|_| Self::default())
     }
 
-    /// Save settings to DB
-    pub fn save(&self, conn: &mut SqliteConnection) {
-        let _ = db::save_settings(conn, self);
+    /// Save settings to DB, append a revision to `server_settings_history`
+    /// recording what changed against the row it replaces, then publish
+    /// the new snapshot to `shared` and raise `dirty` so any subscribed
+    /// background loop (see [`subscribe`]) picks it up on its next
+    /// iteration instead of after a restart.
+    ///
+    /// Takes `pool` rather than an already-checked-out connection: each
+    /// call grabs its own connection for the duration of the write and
+    /// releases it immediately after, rather than requiring the caller to
+    /// hold one open (and so serialize every other pooled user behind it)
+    /// across however many calls it makes in a row. Prefer
+    /// [`update`](Self::update) when only a subset of fields is changing —
+    /// it validates and writes them as a single transaction instead of
+    /// whatever is currently in `self`.
+    ///
+    /// [`subscribe`]: Self::subscribe
+    pub async fn save(
+        &self,
+        pool: &DbPool,
+        shared: &Arc<RwLock<ServerSettings>>,
+        dirty: &Arc<AtomicBool>,
+        principal: &SettingsPrincipal,
+    ) -> Result<(), SettingsError> {
+        principal.authorize()?;
+
+        let conn = db::get_conn(pool).await?;
+        let new = self.clone();
+        conn.interact(move |conn| -> QueryResult<()> {
+            let previous = db::load_settings(conn).unwrap_or_default();
+            db::save_settings(conn, &new)?;
+            Self::record_history(conn, &previous, &new)?;
+            Ok(())
+        })
+        .await??;
+
+        Self::publish(shared, dirty, self.clone());
+        Ok(())
     }
 
-    /// Update a single field and persist immediately
-    pub fn set_auto_approve(&mut self, conn: &mut SqliteConnection, value: bool) -> QueryResult<usize> {
-        self.auto_approve_devices = value;
-        diesel::update(server_settings::table)
-            .set(server_settings::auto_approve_devices.eq(value))
-            .execute(conn)
+    /// Apply `patch` to the settings row as a single transaction: validate
+    /// every `Some` field against [`ServerSettingsPatch::validate`] first,
+    /// and write nothing at all if any of them fail. Replaces the old
+    /// one-setter-per-field approach (`set_auto_approve` and friends),
+    /// which updated and published each field independently — a caller
+    /// setting several fields at once could briefly publish a
+    /// partially-applied settings value, and nothing stopped e.g. a zero
+    /// `auto_refresh_seconds` from reaching the database and later
+    /// panicking a worker loop's interval math.
+    pub async fn update(
+        pool: &DbPool,
+        shared: &Arc<RwLock<ServerSettings>>,
+        dirty: &Arc<AtomicBool>,
+        principal: &SettingsPrincipal,
+        patch: ServerSettingsPatch,
+    ) -> Result<ServerSettings, SettingsError> {
+        principal.authorize()?;
+        patch.validate()?;
+
+        let conn = db::get_conn(pool).await?;
+        let new = conn
+            .interact(move |conn| -> Result<ServerSettings, diesel::result::Error> {
+                conn.transaction(|conn| {
+                    let previous = db::load_settings(conn).unwrap_or_default();
+                    let mut new = previous.clone();
+                    patch.apply_to(&mut new);
+                    db::save_settings(conn, &new)?;
+                    Self::record_history(conn, &previous, &new)?;
+                    Ok(new)
+                })
+            })
+            .await??;
+
+        Self::publish(shared, dirty, new.clone());
+        Ok(new)
     }
 
-    pub fn set_auto_refresh(&mut self, conn: &mut SqliteConnection, value: bool) -> QueryResult<usize> {
-        self.auto_refresh_enabled = value;
-        diesel::update(server_settings::table)
-            .set(server_settings::auto_refresh_enabled.eq(value))
-            .execute(conn)
+    fn publish(shared: &Arc<RwLock<ServerSettings>>, dirty: &Arc<AtomicBool>, settings: ServerSettings) {
+        *shared.write().unwrap() = settings;
+        dirty.store(true, Ordering::SeqCst);
     }
 
-    pub fn set_auto_refresh_interval(&mut self, conn: &mut SqliteConnection, value: i64) -> QueryResult<usize> {
-        self.auto_refresh_seconds = value;
-        diesel::update(server_settings::table)
-            .set(server_settings::auto_refresh_seconds.eq(value))
+    /// Append a `server_settings_history` row capturing the field names
+    /// that differ between `previous` and `new`, plus a full JSON
+    /// snapshot of each side, so [`rollback_to`](Self::rollback_to) can
+    /// later reconstruct any prior revision.
+    fn record_history(conn: &mut DbBackendConnection, previous: &ServerSettings, new: &ServerSettings) -> QueryResult<usize> {
+        let changed = diff_field_names(previous, new);
+        if changed.is_empty() {
+            return Ok(0);
+        }
+
+        diesel::insert_into(server_settings_history::table)
+            .values((
+                server_settings_history::changed_at.eq(chrono::Utc::now().naive_utc()),
+                server_settings_history::changed_fields.eq(changed.join(",")),
+                server_settings_history::old_values.eq(serde_json::to_string(previous).unwrap_or_default()),
+                server_settings_history::new_values.eq(serde_json::to_string(new).unwrap_or_default()),
+            ))
             .execute(conn)
     }
+
+    /// Recent settings revisions, newest first.
+    pub async fn history(pool: &DbPool, limit: i64) -> Result<Vec<SettingsHistoryRecord>, SettingsError> {
+        let conn = db::get_conn(pool).await?;
+        let rows = conn
+            .interact(move |conn| {
+                server_settings_history::table
+                    .order(server_settings_history::revision.desc())
+                    .limit(limit)
+                    .load::<SettingsHistoryRecord>(conn)
+            })
+            .await??;
+        Ok(rows)
+    }
+
+    /// Reconstruct the settings as they were immediately after `revision`,
+    /// then re-persist and publish them as a new revision — an operator's
+    /// one-call undo for a bad configuration change.
+    pub async fn rollback_to(
+        pool: &DbPool,
+        shared: &Arc<RwLock<ServerSettings>>,
+        dirty: &Arc<AtomicBool>,
+        principal: &SettingsPrincipal,
+        revision: i32,
+    ) -> Result<ServerSettings, SettingsError> {
+        principal.authorize()?;
+
+        let conn = db::get_conn(pool).await?;
+        let row = conn
+            .interact(move |conn| {
+                server_settings_history::table
+                    .find(revision)
+                    .first::<SettingsHistoryRecord>(conn)
+            })
+            .await??;
+
+        let restored: ServerSettings = serde_json::from_str(&row.new_values)?;
+        restored.save(pool, shared, dirty, principal).await?;
+        Ok(restored)
+    }
+
+    /// Hand a background loop a cheap snapshot-based view onto `shared`,
+    /// plus the "something changed" flag the same mutators above raise.
+    /// Call [`SettingsSubscription::poll_dirty`] at the top of each
+    /// iteration and re-`snapshot` (and recompute any derived sleep
+    /// interval) whenever it returns `true`.
+    pub fn subscribe(shared: Arc<RwLock<ServerSettings>>, dirty: Arc<AtomicBool>) -> SettingsSubscription {
+        SettingsSubscription { shared, dirty }
+    }
+}
+
+/// One row of `server_settings_history`, as returned by
+/// [`ServerSettings::history`].
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = server_settings_history)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct SettingsHistoryRecord {
+    pub revision: i32,
+    pub changed_at: chrono::NaiveDateTime,
+    /// Comma-separated field names that differ between `old_values` and
+    /// `new_values`.
+    pub changed_fields: String,
+    /// Full JSON-serialized [`ServerSettings`] immediately before this
+    /// revision.
+    pub old_values: String,
+    /// Full JSON-serialized [`ServerSettings`] immediately after this
+    /// revision — what [`ServerSettings::rollback_to`] restores.
+    pub new_values: String,
+}
+
+/// Field names that differ between `old` and `new`, compared via their
+/// JSON representations so this stays correct as fields are added to
+/// [`ServerSettings`] without needing a matching update here.
+fn diff_field_names(old: &ServerSettings, new: &ServerSettings) -> Vec<String> {
+    let (Some(old_obj), Some(new_obj)) = (
+        serde_json::to_value(old).ok().and_then(|v| v.as_object().cloned()),
+        serde_json::to_value(new).ok().and_then(|v| v.as_object().cloned()),
+    ) else {
+        return Vec::new();
+    };
+
+    new_obj
+        .iter()
+        .filter(|(key, value)| old_obj.get(*key) != Some(*value))
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// See [`ServerSettings::subscribe`].
+#[derive(Clone)]
+pub struct SettingsSubscription {
+    shared: Arc<RwLock<ServerSettings>>,
+    dirty: Arc<AtomicBool>,
+}
+
+impl SettingsSubscription {
+    /// Current settings snapshot, cloned out from behind the lock so the
+    /// caller never holds the `RwLock` guard across an `.await`.
+    pub fn snapshot(&self) -> ServerSettings {
+        self.shared.read().unwrap().clone()
+    }
+
+    /// Returns `true` (and clears the flag) if a mutator has published a
+    /// change since the last call. A background loop should treat this as
+    /// "re-read `snapshot()` before computing the next sleep".
+    pub fn poll_dirty(&self) -> bool {
+        self.dirty.swap(false, Ordering::SeqCst)
+    }
 }
 
 impl Default for ServerSettings {
@@ -58,7 +382,16 @@ impl Default for ServerSettings {
             auto_refresh_seconds: 30,
             default_action_ttl_seconds: 3600,
             action_polling_enabled: true,
-            ping_target_ip: "8.8.8.8".to_string(),
+            jwt_secret: std::env::var("JWT_SECRET")
+                .unwrap_or_else(|_| "dev-insecure-jwt-secret-change-me".to_string()),
+            access_token_ttl_seconds: 15 * 60,
+            storage_backend: "filesystem".to_string(),
+            storage_local_path: "/opt/patchpilot_server/storage".to_string(),
+            s3_endpoint: String::new(),
+            s3_bucket: String::new(),
+            s3_access_key: String::new(),
+            s3_secret_key: String::new(),
+            s3_region: "us-east-1".to_string(),
         }
     }
 }
\ No newline at end of file
@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock, Mutex};
+use std::sync::atomic::AtomicBool;
 use sysinfo::System;
 use chrono::NaiveDateTime;
 
 use crate::settings::ServerSettings;
 use crate::db::DbPool;
+use crate::relay::RelayRegistry;
+use crate::storage::ObjectStore;
 
 pub struct SystemState {
     pub db_pool: DbPool,
@@ -28,4 +31,13 @@ pub struct AppState {
     pub system: Arc<SystemState>,
     pub pending_devices: Arc<RwLock<HashMap<String, NaiveDateTime>>>,
     pub settings: Arc<RwLock<ServerSettings>>,
+    /// Raised by every `ServerSettings` mutator (see `ServerSettings::subscribe`)
+    /// so a background loop holding a `SettingsSubscription` notices a change
+    /// within one tick instead of only after a restart.
+    pub settings_dirty: Arc<AtomicBool>,
+    /// Live outbound channels for agents holding open a relay connection.
+    pub relay: Arc<RelayRegistry>,
+    /// Object-storage backend for system-info snapshots and uploaded patch
+    /// artifacts — filesystem or S3, chosen via `ServerSettings::storage_backend`.
+    pub storage: Arc<dyn ObjectStore>,
 }
@@ -0,0 +1,212 @@
+//! Pluggable object storage for device system-info snapshots and uploaded
+//! patch artifacts. [`ObjectStore`] is the one seam the rest of the server
+//! talks to; which backend sits behind it is picked at startup from
+//! `ServerSettings::storage_backend`, so a self-hosted deployment can stay
+//! on local disk while a cloud deployment points the same code at an
+//! S3-compatible bucket (including MinIO) without touching a call site.
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use aws_sdk_s3::config::{Credentials, Region};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+
+use crate::settings::ServerSettings;
+
+#[rocket::async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<()>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// Stores objects as plain files under `root`, with `key` (which may
+/// contain `/`) mapped directly onto the filesystem path.
+pub struct FilesystemStore {
+    root: PathBuf,
+}
+
+impl FilesystemStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve `key` to a path under `root`, rejecting anything that would
+    /// climb back out of it (`..` segments) so a crafted key can't be used
+    /// to read or write outside the configured storage root.
+    fn resolve(&self, key: &str) -> Result<PathBuf> {
+        if key.split('/').any(|segment| segment == "..") {
+            return Err(anyhow!("object key must not contain '..' segments: {key}"));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[rocket::async_trait]
+impl ObjectStore for FilesystemStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<()> {
+        let path = self.resolve(key)?;
+        if let Some(parent) = path.parent() {
+            rocket::tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("creating parent dirs for {}", path.display()))?;
+        }
+        rocket::tokio::fs::write(&path, bytes)
+            .await
+            .with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.resolve(key)?;
+        rocket::tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("reading {}", path.display()))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let base = self.resolve(prefix)?;
+        let mut keys = Vec::new();
+        collect_filesystem_keys(&self.root, &base, &mut keys).await?;
+        Ok(keys)
+    }
+}
+
+async fn collect_filesystem_keys(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut entries = rocket::tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("listing {}", dir.display()))?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() {
+            Box::pin(collect_filesystem_keys(root, &path, out)).await?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            out.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Stores objects in an S3-compatible bucket, pointed at a real AWS region
+/// or a self-hosted endpoint such as MinIO via `endpoint`.
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        access_key: &str,
+        secret_key: &str,
+        region: &str,
+    ) -> Self {
+        let credentials = Credentials::new(access_key, secret_key, None, None, "patchpilot-settings");
+
+        let mut config_builder = aws_sdk_s3::Config::builder()
+            .region(Region::new(region.to_string()))
+            .credentials_provider(credentials)
+            // MinIO and most other S3-compatible stores expect path-style
+            // bucket addressing (`https://host/bucket/key`) rather than the
+            // virtual-hosted style AWS defaults to.
+            .force_path_style(true);
+
+        if !endpoint.is_empty() {
+            config_builder = config_builder.endpoint_url(endpoint);
+        }
+
+        Self {
+            client: S3Client::from_conf(config_builder.build()),
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl ObjectStore for S3Store {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await
+            .with_context(|| format!("putting s3://{}/{}", self.bucket, key))?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .with_context(|| format!("getting s3://{}/{}", self.bucket, key))?;
+
+        let bytes = output
+            .body
+            .collect()
+            .await
+            .with_context(|| format!("reading body of s3://{}/{}", self.bucket, key))?;
+        Ok(bytes.into_bytes().to_vec())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .send()
+            .await
+            .with_context(|| format!("listing s3://{}/{}", self.bucket, prefix))?;
+
+        Ok(output
+            .contents()
+            .iter()
+            .filter_map(|obj| obj.key().map(str::to_string))
+            .collect())
+    }
+}
+
+/// Build the configured [`ObjectStore`] from `settings`. Called once at
+/// startup (see `main::rocket`) and managed as `Arc<dyn ObjectStore>`.
+pub fn build_object_store(settings: &ServerSettings) -> Arc<dyn ObjectStore> {
+    match settings.storage_backend.as_str() {
+        "s3" => Arc::new(S3Store::new(
+            &settings.s3_endpoint,
+            &settings.s3_bucket,
+            &settings.s3_access_key,
+            &settings.s3_secret_key,
+            &settings.s3_region,
+        )),
+        _ => Arc::new(FilesystemStore::new(&settings.storage_local_path)),
+    }
+}
+
+/// Persist a device's system-info snapshot under
+/// `snapshots/<serial>/<timestamp>.json`. `serial` is the device's own id
+/// rather than a real hardware serial (neither `sysinfo` nor this payload
+/// expose one). The client's `system_info::get_system_info` has no real
+/// serial either and falls back to the hostname for its `serial_number`.
+pub async fn store_system_info_snapshot(
+    store: &Arc<dyn ObjectStore>,
+    serial: &str,
+    system_info: &serde_json::Value,
+) -> Result<()> {
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%S%.fZ");
+    let key = format!("snapshots/{serial}/{timestamp}.json");
+    let bytes = serde_json::to_vec(system_info).context("serializing system-info snapshot")?;
+    store.put(&key, bytes).await
+}
@@ -1,55 +1,68 @@
-use chrono::Utc;
-use diesel::prelude::*;
-use rocket::tokio;
-use std::time::Duration;
-
-use crate::db::pool::DbPool;
-use crate::models::{Action, NewHistoryRecord};
-use crate::schema::{actions, action_targets, history_log};
-
-pub fn spawn_action_ttl_sweeper(pool: DbPool) {
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(10)).await;
-
-            let pool = pool.clone();
-            let _ = tokio::task::spawn_blocking(move || {
-                let mut conn = pool.get().ok()?;
-
-                let expired = actions::table
-                    .filter(actions::expires_at.le(Utc::now().naive_utc()))
-                    .filter(actions::canceled.eq(false))
-                    .load::<Action>(&mut conn)
-                    .ok()?;
-
-                for act in expired {
-                    let history = NewHistoryRecord::new(
-                        Some(act.id.clone()),
-                        None,
-                        act.author.clone(),
-                        "expired".into(),
-                        None,
-                    );
-
-                    let _ = diesel::insert_into(history_log::table)
-                        .values(&history)
-                        .execute(&mut conn);
-
-                    let _ = diesel::update(actions::table.filter(actions::id.eq(&act.id)))
-                        .set(actions::canceled.eq(true))
-                        .execute(&mut conn);
-
-                    let _ = diesel::update(
-                        action_targets::table
-                            .filter(action_targets::action_id.eq(&act.id))
-                            .filter(action_targets::status.eq("pending")),
-                    )
-                    .set(action_targets::status.eq("expired"))
-                    .execute(&mut conn);
-                }
-
-                Some(())
-            }).await;
-        }
-    });
-}
+use chrono::Utc;
+use diesel::prelude::*;
+use std::time::Duration;
+
+use crate::background::{BackgroundRunner, WorkerError};
+use crate::db::{self, DbPool};
+use crate::models::{Action, NewHistoryRecord};
+use crate::schema::{actions, action_targets, history_log};
+use crate::settings::SettingsSubscription;
+
+/// Registered with a [`SettingsSubscription`] so toggling
+/// `action_polling_enabled` off pauses the sweep (and back on resumes it)
+/// within one tick, without needing a restart.
+pub fn spawn_action_ttl_sweeper(runner: &BackgroundRunner, pool: DbPool, subscription: SettingsSubscription) {
+    runner.register_dynamic(
+        "action_ttl_sweeper",
+        subscription.clone(),
+        |_settings| Duration::from_secs(10),
+        move || {
+            let pool = pool.clone();
+            let subscription = subscription.clone();
+            async move {
+                if !subscription.snapshot().action_polling_enabled {
+                    return Ok(());
+                }
+
+                let conn = db::get_conn(&pool).await?;
+
+                conn.interact(move |conn| -> Result<(), WorkerError> {
+                    let expired = actions::table
+                        .filter(actions::expires_at.le(Utc::now().naive_utc()))
+                        .filter(actions::canceled.eq(false))
+                        .load::<Action>(conn)?;
+
+                    for act in expired {
+                        let history = NewHistoryRecord::new(
+                            Some(act.id.clone()),
+                            None,
+                            act.author.clone(),
+                            "expired".into(),
+                            None,
+                        );
+
+                        diesel::insert_into(history_log::table)
+                            .values(&history)
+                            .execute(conn)?;
+
+                        diesel::update(actions::table.filter(actions::id.eq(&act.id)))
+                            .set(actions::canceled.eq(true))
+                            .execute(conn)?;
+
+                        diesel::update(
+                            action_targets::table
+                                .filter(action_targets::action_id.eq(&act.id))
+                                .filter(action_targets::status.eq("pending")),
+                        )
+                        .set(action_targets::status.eq("expired"))
+                        .execute(conn)?;
+                    }
+
+                    Ok(())
+                })
+                .await
+                .unwrap_or_else(|e| Err(Box::new(e) as WorkerError))
+            }
+        },
+    );
+}
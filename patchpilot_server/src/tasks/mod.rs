@@ -0,0 +1,7 @@
+pub mod action_ttl;
+pub mod monitor_scan;
+pub mod pending_cleanup;
+
+pub use action_ttl::spawn_action_ttl_sweeper;
+pub use monitor_scan::spawn_monitor_scanner;
+pub use pending_cleanup::spawn_pending_cleanup;
@@ -0,0 +1,93 @@
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use rocket::tokio::task::spawn_blocking;
+
+use crate::background::{BackgroundRunner, WorkerError};
+use crate::db::{self, DbPool};
+use crate::settings::SettingsSubscription;
+
+/// Floor on the tick interval derived from `auto_refresh_seconds`, so a
+/// misconfigured (e.g. `0` or negative) value can't spin this worker.
+const MIN_TICK_SECS: i64 = 5;
+
+/// How long to wait for a single connect attempt before counting that
+/// address family as unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Port probed to test reachability. There's no portable, unprivileged way
+/// to send a real ICMP echo from an unprivileged process (it needs
+/// `CAP_NET_RAW` or a raw socket), so this uses a plain TCP connect
+/// instead — close enough to "is this target up" for the targets this
+/// subsystem is meant for (the default `8.8.8.8` answers HTTPS/DoH here,
+/// and most operator-added hosts run something on 443 too).
+const PROBE_PORT: u16 = 443;
+
+/// Periodically probes every configured [`crate::models::PingTarget`] over
+/// both IPv4 and IPv6 and records the result, replacing the old
+/// `ServerSettings::ping_target_ip` single-address check. Registered with
+/// a [`SettingsSubscription`] the same way `tasks::pending_cleanup` is, so
+/// a change to `auto_refresh_seconds` reschedules this worker within one
+/// tick instead of requiring a restart.
+pub fn spawn_monitor_scanner(runner: &BackgroundRunner, pool: DbPool, subscription: SettingsSubscription) {
+    runner.register_dynamic(
+        "monitor_scan",
+        subscription,
+        |settings| Duration::from_secs(settings.auto_refresh_seconds.max(MIN_TICK_SECS) as u64),
+        move || {
+            let pool = pool.clone();
+            async move {
+                let conn = db::get_conn(&pool).await?;
+                let targets = conn
+                    .interact(|conn| -> Result<_, WorkerError> { Ok(db::list_ping_targets(conn)?) })
+                    .await
+                    .unwrap_or_else(|e| Err(Box::new(e) as WorkerError))?;
+
+                for target in targets {
+                    let target_id = target.id;
+                    let (ipv4_reachable, ipv6_reachable, rtt_ms) = spawn_blocking(move || probe(&target.address))
+                        .await
+                        .map_err(|e| Box::new(e) as WorkerError)?;
+
+                    let conn = db::get_conn(&pool).await?;
+                    conn.interact(move |conn| -> Result<(), WorkerError> {
+                        Ok(db::record_monitor_result(conn, target_id, ipv4_reachable, ipv6_reachable, rtt_ms)?)
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(Box::new(e) as WorkerError))?;
+                }
+
+                Ok(())
+            }
+        },
+    );
+}
+
+/// Resolve `address` and attempt a TCP connect to [`PROBE_PORT`] over each
+/// address family found, returning whether each family answered and the
+/// round-trip time of the first successful attempt.
+fn probe(address: &str) -> (Option<bool>, Option<bool>, Option<f32>) {
+    let candidates: Vec<SocketAddr> = (address, PROBE_PORT)
+        .to_socket_addrs()
+        .map(|addrs| addrs.collect())
+        .unwrap_or_default();
+
+    let mut ipv4_reachable = None;
+    let mut ipv6_reachable = None;
+    let mut rtt_ms = None;
+
+    for addr in candidates {
+        let started = Instant::now();
+        let reachable = TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok();
+        let elapsed_ms = started.elapsed().as_secs_f32() * 1000.0;
+
+        let family_reachable = if addr.is_ipv4() { &mut ipv4_reachable } else { &mut ipv6_reachable };
+        *family_reachable = Some(family_reachable.unwrap_or(false) || reachable);
+
+        if reachable && rtt_ms.is_none() {
+            rtt_ms = Some(elapsed_ms);
+        }
+    }
+
+    (ipv4_reachable, ipv6_reachable, rtt_ms)
+}
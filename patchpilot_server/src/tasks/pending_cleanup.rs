@@ -1,31 +1,47 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use rocket::tokio;
-
-use crate::state::AppState;
-
-pub fn spawn_pending_cleanup(state: Arc<AppState>) {
-    tokio::spawn(async move {
-        let mut last_checkin: HashMap<String, Instant> = HashMap::new();
-
-        loop {
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            let now = Instant::now();
-
-            let mut pending = state.pending_devices.write().unwrap();
-            for id in pending.keys() {
-                last_checkin.insert(id.clone(), now);
-            }
-
-            pending.retain(|id, _| {
-                last_checkin
-                    .get(id)
-                    .map(|t| now.duration_since(*t) < Duration::from_secs(15))
-                    .unwrap_or(false)
-            });
-
-            last_checkin.retain(|id, _| pending.contains_key(id));
-        }
-    });
-}
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::background::BackgroundRunner;
+use crate::settings::ServerSettings;
+use crate::state::AppState;
+
+/// Floor on the tick interval derived from `auto_refresh_seconds`, so a
+/// misconfigured (e.g. `0` or negative) value can't spin this worker.
+const MIN_TICK_SECS: i64 = 5;
+
+pub fn spawn_pending_cleanup(runner: &BackgroundRunner, state: Arc<AppState>) {
+    let last_checkin: Arc<Mutex<HashMap<String, Instant>>> = Arc::new(Mutex::new(HashMap::new()));
+    let subscription = ServerSettings::subscribe(state.settings.clone(), state.settings_dirty.clone());
+
+    runner.register_dynamic(
+        "pending_device_cleanup",
+        subscription,
+        |settings| Duration::from_secs(settings.auto_refresh_seconds.max(MIN_TICK_SECS) as u64),
+        move || {
+            let state = state.clone();
+            let last_checkin = last_checkin.clone();
+            async move {
+                let now = Instant::now();
+
+                let mut pending = state.pending_devices.write().unwrap();
+                let mut last_checkin = last_checkin.lock().unwrap();
+
+                for id in pending.keys() {
+                    last_checkin.insert(id.clone(), now);
+                }
+
+                pending.retain(|id, _| {
+                    last_checkin
+                        .get(id)
+                        .map(|t| now.duration_since(*t) < Duration::from_secs(15))
+                        .unwrap_or(false)
+                });
+
+                last_checkin.retain(|id, _| pending.contains_key(id));
+
+                Ok(())
+            }
+        },
+    );
+}
@@ -0,0 +1,172 @@
+//! Bearer-token authentication for headless/agent callers. This is
+//! deliberately separate from the cookie-based `auth::AuthUser` guard:
+//! agents polling over the network have no browser session to carry a
+//! cookie, so they authenticate with a short-lived JWT access token instead,
+//! refreshed via a long-lived, revocable refresh token (see
+//! `db::store_refresh_token`/`db::find_valid_refresh_token`).
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::auth::UserRole;
+use crate::db::DbPool;
+use crate::state::AppState;
+
+/// How long an issued refresh token is valid for before it must be
+/// re-issued via `/auth/token`.
+pub const REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Claims embedded in an access token. `sub` is the `users.id` of the
+/// account the token was issued to — a device authenticates as whichever
+/// account its operator configured it with, there's no separate device
+/// identity in the token itself.
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: i32,
+    username: String,
+    roles: Vec<String>,
+    exp: usize,
+}
+
+/// Sign a new access token for `user_id`/`username` with `roles` baked in,
+/// using the signing secret and TTL configured in `ServerSettings`.
+pub fn issue_access_token(
+    user_id: i32,
+    username: &str,
+    roles: &[String],
+    app_state: &AppState,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let settings = app_state.settings.read().unwrap();
+    let claims = Claims {
+        sub: user_id,
+        username: username.to_string(),
+        roles: roles.to_vec(),
+        exp: (Utc::now() + Duration::seconds(settings.access_token_ttl_seconds)).timestamp() as usize,
+    };
+
+    encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(settings.jwt_secret.as_bytes()),
+    )
+}
+
+/// Generate a fresh opaque refresh token: 32 random bytes, URL-safe
+/// base64-encoded. The caller is responsible for persisting its hash (see
+/// `hash_refresh_token`) — the raw value returned here is only ever handed
+/// to the agent, never stored.
+pub fn generate_refresh_token() -> String {
+    use base64::Engine;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a refresh token for storage/lookup, so a leaked `refresh_tokens`
+/// table doesn't hand out usable tokens.
+pub fn hash_refresh_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// An authenticated agent/headless caller, extracted from a validated
+/// `Authorization: Bearer <jwt>` header. Mirrors `auth::AuthUser`'s shape
+/// (id/username/roles + `has_role`) so route handlers can treat the two
+/// guards interchangeably where it makes sense.
+#[derive(Debug, Clone)]
+pub struct TokenAuth {
+    pub user_id: i32,
+    pub username: String,
+    pub roles: Vec<UserRole>,
+}
+
+impl TokenAuth {
+    pub fn has_role(&self, role: &UserRole) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TokenAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+
+        let Some(token) = token else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+
+        let Outcome::Success(app_state) = req.guard::<&State<Arc<AppState>>>().await else {
+            return Outcome::Failure((Status::InternalServerError, ()));
+        };
+        let jwt_secret = app_state.settings.read().unwrap().jwt_secret.clone();
+
+        let claims = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(jwt_secret.as_bytes()),
+            &Validation::new(Algorithm::HS256),
+        );
+
+        let Ok(data) = claims else {
+            return Outcome::Failure((Status::Unauthorized, ()));
+        };
+
+        let roles = data
+            .claims
+            .roles
+            .into_iter()
+            .map(|r| match r.as_str() {
+                "Admin" => UserRole::Admin,
+                "Manager" => UserRole::Manager,
+                _ => UserRole::User,
+            })
+            .collect();
+
+        Outcome::Success(TokenAuth {
+            user_id: data.claims.sub,
+            username: data.claims.username,
+            roles,
+        })
+    }
+}
+
+/// Mint a fresh access+refresh pair for `user_id`/`username`/`roles`,
+/// persisting the refresh token's hash via `pool`. Shared by the initial
+/// `/auth/token` login and `/auth/refresh`'s rotation.
+pub async fn issue_token_pair(
+    pool: &State<DbPool>,
+    app_state: &State<Arc<AppState>>,
+    user_id: i32,
+    username: &str,
+    roles: &[String],
+) -> Result<(String, String), Status> {
+    let access_token = issue_access_token(user_id, username, roles, app_state)
+        .map_err(|_| Status::InternalServerError)?;
+
+    let refresh_token = generate_refresh_token();
+    let refresh_hash = hash_refresh_token(&refresh_token);
+    let issued_at = Utc::now().naive_utc();
+    let expires_at = issued_at + Duration::seconds(REFRESH_TOKEN_TTL_SECS);
+
+    let conn = pool.get().await.map_err(|_| Status::InternalServerError)?;
+    conn.interact(move |conn| {
+        crate::db::store_refresh_token(conn, user_id, &refresh_hash, issued_at, expires_at)
+    })
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok((access_token, refresh_token))
+}
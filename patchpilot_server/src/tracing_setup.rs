@@ -0,0 +1,37 @@
+//! Global `tracing` subscriber setup, replacing the old `flexi_logger`-backed
+//! `log` facade. Every span/event — the request fairing, a Diesel error, a
+//! `log_audit` failure — now goes through one subscriber instead of a mix of
+//! `log::info!`/`eprintln!` calls that couldn't be correlated with each other.
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Console layer selected via `PATCHPILOT_LOG_FORMAT`: `json` for
+/// machine-readable ingestion, anything else (including unset) for a
+/// human-readable nested trace view.
+fn console_format() -> String {
+    std::env::var("PATCHPILOT_LOG_FORMAT").unwrap_or_else(|_| "pretty".to_string())
+}
+
+/// Install the global subscriber and start the daily-rotating log file under
+/// `logs/`. Returns a guard that must be held for the life of the process —
+/// dropping it stops the non-blocking file writer from flushing.
+pub fn init() -> WorkerGuard {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let file_appender = tracing_appender::rolling::daily("logs", "patchpilot.log");
+    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+    let file_layer = fmt::layer()
+        .json()
+        .with_writer(file_writer)
+        .with_ansi(false);
+
+    let registry = tracing_subscriber::registry().with(filter).with(file_layer);
+
+    if console_format() == "json" {
+        registry.with(fmt::layer().json()).init();
+    } else {
+        registry.with(tracing_forest::ForestLayer::default()).init();
+    }
+
+    guard
+}